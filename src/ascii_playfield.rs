@@ -0,0 +1,94 @@
+use rstris::block::Block;
+use rstris::playfield::Playfield;
+
+use crate::game::GARBAGE_BLOCK_ID;
+
+//
+// Playfields as text, one line per row from the top. Empty blocks are
+// written as '.' and set blocks as their id (1-9). Blocks with higher ids
+// are written as '#', which reads back as a garbage block. E.g:
+//
+//   ....
+//   .1..
+//   11.7
+//
+pub fn playfield_from_ascii(text: &str) -> Result<Playfield, String> {
+    let rows: Vec<&str> = text
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let width = match rows.first() {
+        Some(row) => row.chars().count(),
+        None => return Err("Playfield has no rows".to_string()),
+    };
+    let mut pf = Playfield::new("Playfield", width as u32, rows.len() as u32);
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != width {
+            return Err(format!(
+                "Row {} is {} blocks wide, expected {}",
+                y,
+                row.chars().count(),
+                width
+            ));
+        }
+        for (x, c) in row.chars().enumerate() {
+            let block = match c {
+                '.' => Block::Clear,
+                '1'..='9' => Block::Set(c as u8 - b'0'),
+                '#' => Block::Set(GARBAGE_BLOCK_ID),
+                _ => return Err(format!("Invalid block '{}' at row {}", c, y)),
+            };
+            pf.set_block((x as i32, y as i32).into(), block);
+        }
+    }
+    Ok(pf)
+}
+
+pub fn playfield_to_ascii(pf: &Playfield) -> String {
+    let mut text = String::new();
+    for y in 0..pf.height() as i32 {
+        for x in 0..pf.width() as i32 {
+            text.push(match pf.get_block((x, y).into()) {
+                Block::Set(id) if id < 10 => (b'0' + id) as char,
+                Block::Set(_) => '#',
+                Block::Clear => '.',
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = "....\n.1..\n11.7\n";
+        let pf = playfield_from_ascii(text).unwrap();
+        assert_eq!(pf.width(), 4);
+        assert_eq!(pf.height(), 3);
+        assert!(matches!(pf.get_block((1, 1).into()), Block::Set(1)));
+        assert!(matches!(pf.get_block((2, 2).into()), Block::Clear));
+        assert_eq!(playfield_to_ascii(&pf), text);
+    }
+
+    #[test]
+    fn other_blocks() {
+        let pf = playfield_from_ascii("#.").unwrap();
+        assert!(matches!(pf.get_block((0, 0).into()), Block::Set(8)));
+
+        let mut pf = Playfield::new("Playfield", 2, 1);
+        pf.set_block((1, 0).into(), Block::Set(12));
+        assert_eq!(playfield_to_ascii(&pf), ".#\n");
+    }
+
+    #[test]
+    fn invalid_text() {
+        assert!(playfield_from_ascii("").is_err());
+        assert!(playfield_from_ascii("...\n..").is_err());
+        assert!(playfield_from_ascii("..x").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use rstris::figure::Figure;
+use rstris::playfield::Playfield;
+use rstris::position::Position;
+
+use crate::computer_player::*;
+use crate::game::Game;
+
+//
+// Search over placings of the current figure followed by the previewed
+// figures. Only the best boards (by summed evaluation) are kept at each
+// depth.
+//
+#[derive(Debug, Clone, Copy)]
+pub struct BeamSearch {
+    // Number of boards kept at each depth
+    pub width: usize,
+    // Number of previewed figures placed after the current one
+    pub depth: usize,
+}
+
+// Board reached by placing figures, starting with one of the placings of
+// the current figure
+struct Node {
+    pf: Playfield,
+    first: usize,
+    eval: f32,
+}
+
+// Placing of a figure on the board of a node, its board not yet built
+struct Candidate {
+    node: usize,
+    first: usize,
+    pos: Position,
+    eval: f32,
+}
+
+impl Node {
+    fn new(pf: &Playfield, fig: &Figure, candidate: &Candidate) -> Self {
+        let mut pf = pf.clone();
+        place_figure(&mut pf, fig, candidate.pos);
+        Node {
+            pf,
+            first: candidate.first,
+            eval: candidate.eval,
+        }
+    }
+}
+
+fn keep_best(candidates: &mut Vec<Candidate>, width: usize) {
+    candidates.sort_by(|a, b| b.eval.total_cmp(&a.eval));
+    candidates.truncate(width);
+}
+
+impl BeamSearch {
+    //
+    // Rate each placing of the current figure by the best board it leads
+    // to. Placings whose boards were all dropped from the beam keep their
+    // own evaluation, below any of those still in it.
+    //
+    pub fn rate_placings<T>(
+        &self,
+        com_type: &mut T,
+        game: &Game,
+        fig: &Figure,
+        placings: &mut [EvalPosition],
+    ) where
+        T: ComputerType,
+    {
+        let width = self.width.max(1);
+        let mut candidates: Vec<Candidate> = placings
+            .iter()
+            .enumerate()
+            .map(|(index, placing)| Candidate {
+                node: 0,
+                first: index,
+                pos: placing.pos,
+                eval: placing.eval,
+            })
+            .collect();
+        keep_best(&mut candidates, width);
+        let mut beam: Vec<Node> = candidates
+            .iter()
+            .map(|candidate| Node::new(game.playfield(), fig, candidate))
+            .collect();
+
+        let mut next_placings = Vec::new();
+        for next_fig in game.preview().iter().take(self.depth) {
+            candidates.clear();
+            for (index, node) in beam.iter().enumerate() {
+                find_reachable_placings(&mut next_placings, game, &node.pf, next_fig);
+                com_type.init_eval(&node.pf, next_placings.len());
+                for pos in &next_placings {
+                    let eval = com_type.eval_placing(&node.pf, next_fig, *pos);
+                    candidates.push(Candidate {
+                        node: index,
+                        first: node.first,
+                        pos: *pos,
+                        eval: node.eval + eval,
+                    });
+                }
+            }
+            if candidates.is_empty() {
+                // Nowhere to put the figure, rate by the boards so far
+                break;
+            }
+            keep_best(&mut candidates, width);
+            beam = candidates
+                .iter()
+                .map(|candidate| Node::new(&beam[candidate.node].pf, next_fig, candidate))
+                .collect();
+        }
+
+        let mut best_evals: Vec<Option<f32>> = vec![None; placings.len()];
+        for node in &beam {
+            let best_eval = &mut best_evals[node.first];
+            *best_eval = Some(best_eval.map_or(node.eval, |best| best.max(node.eval)));
+        }
+        for (placing, best_eval) in placings.iter_mut().zip(best_evals) {
+            placing.eval = best_eval.unwrap_or(placing.eval + NO_PLACING_EVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameConfig;
+    use crate::test_helpers::*;
+
+    // Rates a placing by the lines it fills and nothing else
+    struct FullLines;
+
+    impl ComputerType for FullLines {
+        fn init_eval(&mut self, _pf: &Playfield, _avail_placings: usize) {}
+
+        fn eval_placing(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> f32 {
+            let mut pf = pf.clone();
+            fig.place(&mut pf, pos);
+            pf.locked_lines().len() as f32
+        }
+    }
+
+    #[test]
+    fn rate_by_previewed_figures() {
+        let o = figure(O);
+        let game = sequence_game(
+            "......\n......\n11....\n11....\n",
+            vec![o.clone()],
+            GameConfig::default(),
+        );
+        let mut pos_list = Vec::new();
+        find_reachable_placings(&mut pos_list, &game, game.playfield(), &o);
+        let mut placings: Vec<EvalPosition> = pos_list
+            .iter()
+            .map(|pos| EvalPosition {
+                pos: *pos,
+                eval: FullLines.eval_placing(game.playfield(), &o, *pos),
+            })
+            .collect();
+        assert!(placings.iter().all(|placing| placing.eval == 0.0));
+
+        // No placing fills a line on its own, but one next to either side
+        // of the bottom leaves room for the next figure to fill two. The
+        // beam is wide enough to keep every board.
+        let beam_search = BeamSearch {
+            width: usize::MAX,
+            depth: 1,
+        };
+        beam_search.rate_placings(&mut FullLines, &game, &o, &mut placings);
+        for placing in &placings {
+            let pos = placing.pos;
+            let expected = if pos.y() == 2 && (pos.x() == 2 || pos.x() == 4) {
+                2.0
+            } else {
+                0.0
+            };
+            assert_eq!(placing.eval, expected);
+        }
+    }
+}
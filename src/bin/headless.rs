@@ -0,0 +1,91 @@
+//
+// Let the computer player play seeded games without a browser and print
+// the average number of lines cleared for different evaluations and
+// searches.
+//
+// Usage: headless [games] [max figures per game] [player]
+//
+// Only players with the given text in their name are run, e.g. 'rollout'.
+//
+use wasmtris::beam_search::BeamSearch;
+use wasmtris::computer_player::{ComputerPlayer, ComputerType};
+use wasmtris::headless::*;
+use wasmtris::jitter_computer::JitterComputer;
+use wasmtris::rollout_computer::RolloutComputer;
+use wasmtris::weighted_computer::WeightedComputer;
+
+struct Runner {
+    games: u64,
+    max_figures: u32,
+    filter: String,
+}
+
+impl Runner {
+    // Play the games with new players, if the name matches the filter
+    fn run<T, F>(&self, name: &str, new_player: F)
+    where
+        T: ComputerType,
+        F: Fn(u64) -> ComputerPlayer<T>,
+    {
+        if !name.contains(&self.filter) {
+            return;
+        }
+        let mut total_lines = 0;
+        let mut total_figures = 0;
+        let mut game_overs = 0;
+        for seed in 0..self.games {
+            let mut game = new_game(10, 20, headless_config(), seed);
+            let outcome = play_game(&mut game, &mut new_player(seed), self.max_figures);
+            total_lines += outcome.lines_cleared;
+            total_figures += outcome.figures_placed;
+            if outcome.game_over {
+                game_overs += 1;
+            }
+        }
+        println!(
+            "{}: {:.1} lines, {:.1} figures per game, {} of {} games topped out",
+            name,
+            total_lines as f32 / self.games as f32,
+            total_figures as f32 / self.games as f32,
+            game_overs,
+            self.games
+        );
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let games: u64 = args.next().map_or(20, |arg| arg.parse().expect("games"));
+    let max_figures: u32 = args
+        .next()
+        .map_or(500, |arg| arg.parse().expect("max figures"));
+    let runner = Runner {
+        games,
+        max_figures,
+        filter: args.next().unwrap_or_default(),
+    };
+
+    runner.run("jitter, no lookahead", |_| {
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+        player.set_lookahead(false);
+        player
+    });
+    runner.run("jitter, next figure", |_| {
+        ComputerPlayer::new(2.0, JitterComputer::new())
+    });
+    runner.run("jitter, beam search", |_| {
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+        player.set_beam_search(Some(BeamSearch { width: 8, depth: 4 }));
+        player
+    });
+    runner.run("dellacherie, next figure", |_| {
+        ComputerPlayer::new(2.0, WeightedComputer::dellacherie())
+    });
+    runner.run("el-tetris, next figure", |_| {
+        ComputerPlayer::new(2.0, WeightedComputer::el_tetris())
+    });
+    runner.run("rollout, 4 rollouts of 3 figures", |seed| {
+        let computer = RolloutComputer::new(wasmtris::init_figures(), 4, 3, seed);
+        ComputerPlayer::new(2.0, computer)
+    });
+}
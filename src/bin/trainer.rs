@@ -0,0 +1,246 @@
+//
+// Tune the weights of the weighted computer with the cross-entropy method.
+// Every generation a population of weights is drawn from a normal
+// distribution, each plays the same seeded headless games and the
+// distribution is moved to the best (elite) of them.
+//
+// The state is saved to the checkpoint file after every generation and
+// training continues from it when started again. The best weights so far
+// are written to the weights file, which can be loaded by
+// GameContext::load_weights.
+//
+// Usage: trainer <checkpoint> <weights> [generations] [population]
+//                [games per candidate] [max figures per game]
+//
+use std::fs;
+use std::io::ErrorKind;
+
+use rand::rngs::SmallRng;
+use rand::{RngExt, SeedableRng};
+
+use wasmtris::computer_player::ComputerPlayer;
+use wasmtris::game::GameConfig;
+use wasmtris::headless::*;
+use wasmtris::weighted_computer::*;
+
+// Part of the population the distribution is fitted to
+const ELITE_FRACTION: f32 = 0.1;
+
+// Deviation of the first generation
+const START_DEVIATION: f32 = 10.0;
+
+// Noise added to the deviation to keep it from collapsing early. It
+// decreases over the generations (Szita and Lőrincz).
+fn noise(generation: u32) -> f32 {
+    (4.0 - generation as f32 / 10.0).max(0.0)
+}
+
+struct Checkpoint {
+    generation: u32,
+    mean: [f32; FEATURE_COUNT],
+    deviation: [f32; FEATURE_COUNT],
+    best: [f32; FEATURE_COUNT],
+    best_score: f32,
+}
+
+impl Checkpoint {
+    fn new() -> Self {
+        Checkpoint {
+            generation: 0,
+            mean: [0.0; FEATURE_COUNT],
+            deviation: [START_DEVIATION; FEATURE_COUNT],
+            best: [0.0; FEATURE_COUNT],
+            // Any score beats no weights at all
+            best_score: f32::NEG_INFINITY,
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut checkpoint = Self::new();
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            let values = words
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|err| format!("Bad value for '{}': {}", key, err))?;
+            let scalar = |values: &[f32]| match values {
+                [value] => Ok(*value),
+                _ => Err(format!("Expected a single value for '{}'", key)),
+            };
+            let vector = |values: &[f32]| {
+                let mut vector = [0.0; FEATURE_COUNT];
+                if values.len() != FEATURE_COUNT {
+                    return Err(format!("Expected {} values for '{}'", FEATURE_COUNT, key));
+                }
+                vector.copy_from_slice(values);
+                Ok(vector)
+            };
+            match key {
+                "generation" => checkpoint.generation = scalar(&values)? as u32,
+                "best_score" => checkpoint.best_score = scalar(&values)?,
+                "mean" => checkpoint.mean = vector(&values)?,
+                "deviation" => checkpoint.deviation = vector(&values)?,
+                "best" => checkpoint.best = vector(&values)?,
+                _ => return Err(format!("Unknown key '{}'", key)),
+            }
+        }
+        Ok(checkpoint)
+    }
+
+    fn to_text(&self) -> String {
+        let vector = |values: &[f32]| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        format!(
+            "generation {}\nbest_score {}\nmean {}\ndeviation {}\nbest {}\n",
+            self.generation,
+            self.best_score,
+            vector(&self.mean),
+            vector(&self.deviation),
+            vector(&self.best)
+        )
+    }
+}
+
+// Sample from a normal distribution (Box-Muller)
+fn normal(rng: &mut SmallRng, mean: f32, deviation: f32) -> f32 {
+    let u1: f32 = 1.0 - rng.random::<f32>();
+    let u2: f32 = rng.random();
+    mean + deviation * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+// Average number of lines cleared with the given weights
+fn score(weights: [f32; FEATURE_COUNT], seeds: &[u64], max_figures: u32) -> f32 {
+    let config = GameConfig {
+        line_clear_delay: 0,
+        entry_delay: 0,
+        ..headless_config()
+    };
+    let mut total_lines = 0;
+    for seed in seeds {
+        let mut game = new_game(10, 20, config.clone(), *seed);
+        let computer = WeightedComputer::new(Features::from_array(weights));
+        let mut player = ComputerPlayer::new(2.0, computer);
+        // Only the evaluation is trained, keep the games fast
+        player.set_lookahead(false);
+        total_lines += play_game(&mut game, &mut player, max_figures).lines_cleared;
+    }
+    total_lines as f32 / seeds.len() as f32
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        return Err(
+            "Usage: trainer <checkpoint> <weights> [generations] [population] [games] [max figures]"
+                .to_string(),
+        );
+    }
+    let checkpoint_path = &args[0];
+    let weights_path = &args[1];
+    let number = |index: usize, default: u32| {
+        args.get(index).map_or(Ok(default), |arg| {
+            arg.parse::<u32>()
+                .map_err(|err| format!("Bad argument '{}': {}", arg, err))
+        })
+    };
+    let generations = number(2, 30)?;
+    let population = number(3, 50)?.max(1);
+    let games = number(4, 4)?.max(1);
+    let max_figures = number(5, 300)?;
+
+    let mut checkpoint = match fs::read_to_string(checkpoint_path) {
+        Ok(text) => {
+            let checkpoint = Checkpoint::parse(&text)?;
+            println!("Resuming at generation {}", checkpoint.generation);
+            checkpoint
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Checkpoint::new(),
+        Err(err) => return Err(format!("Failed to read {}: {}", checkpoint_path, err)),
+    };
+
+    let elite = ((population as f32 * ELITE_FRACTION).ceil() as usize).max(1);
+    let end = checkpoint.generation + generations;
+    while checkpoint.generation < end {
+        let generation = checkpoint.generation;
+
+        // Same games for the whole generation, so candidates compare fairly
+        let mut rng = SmallRng::seed_from_u64(u64::from(generation));
+        let seeds: Vec<u64> = (0..games).map(|_| rng.random()).collect();
+
+        let mut candidates: Vec<([f32; FEATURE_COUNT], f32)> = (0..population)
+            .map(|_| {
+                let mut weights = [0.0; FEATURE_COUNT];
+                for (i, weight) in weights.iter_mut().enumerate() {
+                    *weight = normal(&mut rng, checkpoint.mean[i], checkpoint.deviation[i]);
+                }
+                (weights, score(weights, &seeds, max_figures))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        for i in 0..FEATURE_COUNT {
+            let values: Vec<f32> = candidates[..elite].iter().map(|c| c.0[i]).collect();
+            let mean = values.iter().sum::<f32>() / elite as f32;
+            let variance =
+                values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / elite as f32;
+            checkpoint.mean[i] = mean;
+            checkpoint.deviation[i] = variance.sqrt() + noise(generation);
+        }
+        let (best, best_score) = candidates[0];
+        if best_score > checkpoint.best_score {
+            checkpoint.best = best;
+            checkpoint.best_score = best_score;
+            fs::write(weights_path, Features::from_array(best).to_text())
+                .map_err(|err| format!("Failed to write {}: {}", weights_path, err))?;
+        }
+        checkpoint.generation += 1;
+        fs::write(checkpoint_path, checkpoint.to_text())
+            .map_err(|err| format!("Failed to write {}: {}", checkpoint_path, err))?;
+        println!(
+            "Generation {}: best {:.1} lines, elite mean {:.1} lines, best so far {:.1}",
+            generation,
+            best_score,
+            candidates[..elite].iter().map(|c| c.1).sum::<f32>() / elite as f32,
+            checkpoint.best_score
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoint() {
+        let mut checkpoint = Checkpoint::new();
+        checkpoint.generation = 3;
+        checkpoint.mean[0] = -1.5;
+        checkpoint.deviation[1] = 0.25;
+        checkpoint.best[2] = 7.0;
+        let parsed = Checkpoint::parse(&checkpoint.to_text()).unwrap();
+        assert_eq!(parsed.generation, 3);
+        assert_eq!(parsed.mean, checkpoint.mean);
+        assert_eq!(parsed.deviation, checkpoint.deviation);
+        assert_eq!(parsed.best, checkpoint.best);
+        assert_eq!(parsed.best_score, f32::NEG_INFINITY);
+
+        checkpoint.best_score = 12.5;
+        let parsed = Checkpoint::parse(&checkpoint.to_text()).unwrap();
+        assert_eq!(parsed.best_score, 12.5);
+
+        assert!(Checkpoint::parse("generation 1 2\n").is_err());
+        assert!(Checkpoint::parse("mean 1\n").is_err());
+        assert!(Checkpoint::parse("best_score high\n").is_err());
+        assert!(Checkpoint::parse("temperature 1\n").is_err());
+    }
+}
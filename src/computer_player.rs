@@ -5,28 +5,138 @@ use rstris::movement::Movement;
 use rstris::playfield::Playfield;
 use rstris::position::Position;
 
+use rand::rngs::SmallRng;
+use rand::{RngExt, SeedableRng};
+
+use crate::beam_search::*;
+use crate::difficulty::*;
 use crate::game::Game;
+use crate::rotation_system::*;
 
 pub trait ComputerType {
     fn init_eval(&mut self, pf: &Playfield, avail_placings: usize);
     fn eval_placing(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> f32;
+
+    // Number of garbage rows expected to rise from the bottom while the
+    // next figure is played. Called before init_eval.
+    fn set_garbage_pressure(&mut self, _rows: f32) {}
+
+    // Evaluation of a placing split into named terms adding up to it
+    fn explain_placing(
+        &mut self,
+        pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+    ) -> Vec<(&'static str, f32)> {
+        vec![("eval", self.eval_placing(pf, fig, pos))]
+    }
+
+    // Set if the evaluation already plays the figures to come, which makes
+    // the lookahead and beam search of the player unnecessary
+    fn looks_ahead(&self) -> bool {
+        false
+    }
 }
 
-struct EvalPosition {
-    pos: Position,
-    eval: f32,
+// Lets the type of computer be chosen at runtime
+impl<T> ComputerType for Box<T>
+where
+    T: ComputerType + ?Sized,
+{
+    fn init_eval(&mut self, pf: &Playfield, avail_placings: usize) {
+        (**self).init_eval(pf, avail_placings)
+    }
+
+    fn eval_placing(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> f32 {
+        (**self).eval_placing(pf, fig, pos)
+    }
+
+    fn set_garbage_pressure(&mut self, rows: f32) {
+        (**self).set_garbage_pressure(rows)
+    }
+
+    fn explain_placing(
+        &mut self,
+        pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+    ) -> Vec<(&'static str, f32)> {
+        (**self).explain_placing(pf, fig, pos)
+    }
+
+    fn looks_ahead(&self) -> bool {
+        (**self).looks_ahead()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct EvalPosition {
+    pub pos: Position,
+    pub eval: f32,
+}
+
+// Where the computer would place a figure and the moves to get it there
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub pos: Position,
+    pub path: Vec<Movement>,
 }
 
+// A placing with its evaluation and the terms adding up to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub pos: Position,
+    pub eval: f32,
+    pub terms: Vec<(&'static str, f32)>,
+}
+
+// Evaluation of a placing after which the next figure can't be placed
+pub const NO_PLACING_EVAL: f32 = -1000.0;
+
 pub struct ComputerPlayer<T>
 where
     T: ComputerType,
 {
     com_type: T,
     moves_per_down_step: f32,
+    // Rate each placing together with the best placing of the next figure
+    lookahead: bool,
+    // Rate placings by searching the placings of all previewed figures.
+    // Replaces the lookahead when set.
+    beam_search: Option<BeamSearch>,
+    // Delays and mistakes, made by the random generator
+    difficulty: Difficulty,
+    rng: SmallRng,
     last_figure: Option<(Figure, Position)>,
+    figures_placed: u32,
+
+    // Positions the figure is planned to pass, and the playfield the plan
+    // was made for
+    expected_positions: Vec<Position>,
+    plan_pf: Option<Playfield>,
+
+    // Frame the first move of the figure is made at the earliest
+    think_until: u64,
+
+    // Earliest frame of the next move
+    next_move_frame: u64,
+
+    // Frame of the last new figure and average frames per figure
+    last_figure_frame: u64,
+    frames_per_figure: f32,
+
+    // Last hint and the figure and playfield it was worked out for. The
+    // placings are only rated again for a new figure or playfield.
+    hint: Option<Hint>,
+    hint_figure: Option<(Figure, Position)>,
+    hint_figures_placed: u32,
+    hint_pf: Option<Playfield>,
+    hint_placings: Vec<EvalPosition>,
 
     // Some cache variables
     avail_placings: Vec<Position>,
+    next_placings: Vec<Position>,
+    lookahead_pf: Option<Playfield>,
     find_path: FindPath,
     eval_placing: Vec<EvalPosition>,
     path: Vec<Movement>,
@@ -41,35 +151,85 @@ where
         ComputerPlayer {
             moves_per_down_step,
             com_type,
+            lookahead: true,
+            beam_search: None,
+            difficulty: Difficulty::default(),
+            rng: SmallRng::seed_from_u64(0),
             moves_per_level: Vec::new(),
             last_figure: None,
+            figures_placed: 0,
+            expected_positions: Vec::new(),
+            plan_pf: None,
+            think_until: 0,
+            next_move_frame: 0,
+            last_figure_frame: 0,
+            frames_per_figure: 0.0,
+            hint: None,
+            hint_figure: None,
+            hint_figures_placed: 0,
+            hint_pf: None,
+            hint_placings: Vec::new(),
             eval_placing: Vec::new(),
             path: Vec::new(),
             find_path: FindPath::default(),
             avail_placings: Vec::new(),
+            next_placings: Vec::new(),
+            lookahead_pf: None,
         }
     }
 
-    fn figure_move_event(&mut self, game: &mut Game, ticks: u64, _fig: &Figure, pos: Position) {
-        let last_y = match self.last_figure {
-            Some((_, ref last_fig_pos)) => last_fig_pos.y(),
-            None => -1,
-        };
-        let y = pos.y();
-        if y > last_y {
-            let mut move_time = 0;
-            while !self.moves_per_level.is_empty() && y == self.moves_per_level[0].0 {
-                let movement = self.moves_per_level.remove(0);
-                game.add_move(movement.1, ticks + move_time);
-                move_time += (game.down_step_time() as f32 / self.moves_per_down_step) as u64;
-            }
+    // Evaluate placings with another computer type from the next figure on
+    pub fn set_com_type(&mut self, com_type: T) {
+        self.com_type = com_type;
+        self.hint_figure = None;
+    }
+
+    pub fn set_lookahead(&mut self, lookahead: bool) {
+        self.lookahead = lookahead;
+        self.hint_figure = None;
+    }
+
+    pub fn set_beam_search(&mut self, beam_search: Option<BeamSearch>) {
+        self.beam_search = beam_search;
+        self.hint_figure = None;
+    }
+
+    // Play with delays and mistakes. The same seed makes the same
+    // mistakes in the same game.
+    pub fn set_difficulty(&mut self, difficulty: Difficulty, seed: u64) {
+        self.difficulty = difficulty;
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    // Make the moves planned for the levels the figure has reached, one
+    // move interval apart
+    fn make_moves(&mut self, game: &mut Game, frame: u64, pos: Position) {
+        let move_interval = self.move_interval(game.gravity());
+        while !self.moves_per_level.is_empty() && self.moves_per_level[0].0 <= pos.y() {
+            let movement = self.moves_per_level.remove(0);
+            let move_frame = self.next_move_frame.max(frame);
+            game.add_move(movement.1, move_frame);
+            self.next_move_frame = move_frame + move_interval;
+        }
+    }
+
+    // Frames between each move. Never less than a frame so that gravity
+    // gets to act between the moves.
+    fn move_interval(&self, gravity: f32) -> u64 {
+        if gravity <= 0.0 {
+            // Figure doesn't fall, move as fast as allowed
+            return 1 + self.difficulty.move_delay;
         }
+        ((1.0 / gravity) / self.moves_per_down_step).max(1.0) as u64 + self.difficulty.move_delay
     }
 
-    fn new_figure_event(&mut self, _ticks: u64, pf: &Playfield, fig: &Figure, pos: Position) {
+    // Rate all placings of the figure, best first
+    fn rate_placings(&mut self, game: &Game, fig: &Figure, pos: Position) {
+        let pf = game.playfield();
         // Find all possible positions where figure can be placed
         self.avail_placings.clear();
         find_placement(&mut self.avail_placings, pf, fig);
+        retain_reachable(&mut self.avail_placings, game, pos);
 
         // Evaluate all placings to find the best one
         self.com_type.init_eval(pf, self.avail_placings.len());
@@ -82,61 +242,821 @@ where
             };
             self.eval_placing.push(eval_pos);
         }
-        self.eval_placing
-            .sort_by(|a, b| b.eval.partial_cmp(&a.eval).unwrap());
+        if self.com_type.looks_ahead() {
+            // Nothing to add
+        } else if let Some(beam_search) = self.beam_search {
+            beam_search.rate_placings(&mut self.com_type, game, fig, &mut self.eval_placing);
+        } else if let (true, Some(next_fig)) = (self.lookahead, game.next_figure()) {
+            for i in 0..self.eval_placing.len() {
+                let eval = self.next_figure_eval(game, fig, self.eval_placing[i].pos, next_fig);
+                self.eval_placing[i].eval += eval;
+            }
+        }
+        self.eval_placing.sort_by(|a, b| b.eval.total_cmp(&a.eval));
+    }
+
+    // Find a path to the first of the rated placings that can be reached.
+    // Returns the placing, with the path left in order in self.path.
+    fn find_target_path(
+        &mut self,
+        game: &Game,
+        fig: &Figure,
+        pos: Position,
+        placings: &[EvalPosition],
+    ) -> Option<Position> {
+        let gravity = game.gravity();
+        // Number of levels the figure falls between each of our moves
+        let levels_per_move = gravity * self.move_interval(gravity) as f32;
+
+        let height = game.playfield().height() as f32;
+        // Figures that don't fall can still only make so many moves
+        let max_moves = game.playfield().width() as usize + 4;
 
-        // Find a path to first (and best) available placing
         self.path.clear();
-        for eval_pos in &self.eval_placing {
-            self.find_path.search(
-                &mut self.path,
-                pf,
-                fig,
-                pos,
-                eval_pos.pos,
-                self.moves_per_down_step,
-            );
+        for eval_pos in placings {
+            if levels_per_move >= height {
+                // Figure will be on the ground before each move
+                find_grounded_path(&mut self.path, game, fig, pos, eval_pos.pos);
+            } else if levels_per_move >= 1.0
+                || game.rotation() != Rotation::Basic
+                || game.move_step() > 1
+            {
+                // Falling several levels per move, or rotations and move
+                // steps FindPath doesn't know about
+                let moves_per_level = ((1.0 / levels_per_move) as usize).clamp(1, max_moves);
+                find_game_path(
+                    &mut self.path,
+                    game,
+                    fig,
+                    pos,
+                    eval_pos.pos,
+                    moves_per_level,
+                    levels_per_move.ceil().max(1.0) as usize,
+                );
+            } else {
+                self.find_path.search(
+                    &mut self.path,
+                    game.playfield(),
+                    fig,
+                    pos,
+                    eval_pos.pos,
+                    1.0 / levels_per_move,
+                );
+            }
             if !self.path.is_empty() {
-                break;
+                self.path.reverse();
+                return Some(eval_pos.pos);
             }
         }
+        None
+    }
 
-        self.moves_per_level.clear();
-        if !self.path.is_empty() {
-            self.path.reverse();
+    //
+    // Plan the moves of the figure from where it will be after falling the
+    // given number of levels. The positions the figure will pass are kept
+    // to tell when it has left the plan.
+    //
+    fn plan(&mut self, game: &Game, fig: &Figure, pos: Position, think_levels: u32) {
+        self.rate_placings(game, fig, pos);
+        if !self.eval_placing.is_empty() {
+            // Maybe try a worse placing first
+            let evals: Vec<f32> = self.eval_placing.iter().map(|e| e.eval).collect();
+            let pick = softmax_pick(&evals, self.difficulty.temperature, &mut self.rng);
+            let picked = self.eval_placing.remove(pick);
+            self.eval_placing.insert(0, picked);
+        }
+
+        // The figure keeps falling while we think, plan from where it will be
+        let mut pos = pos;
+        for _ in 0..think_levels.min(game.playfield().height()) {
+            match game.try_move(fig, pos, Movement::MoveDown) {
+                Some(below) => pos = below,
+                None => break,
+            }
+        }
 
+        let pf = game.playfield();
+        self.plan_pf.get_or_insert_with(|| pf.clone()).copy(pf);
+        self.expected_positions.clear();
+        self.moves_per_level.clear();
+        let placings = std::mem::take(&mut self.eval_placing);
+        let target = self.find_target_path(game, fig, pos, &placings);
+        self.eval_placing = placings;
+        if let Some(target) = target {
             // Convert the path from being in exact Movements to
             // describe the sideways/rotational movements per height level
-            path_to_moves_per_level(&mut self.moves_per_level, &self.path);
+            path_to_moves_per_level(&mut self.moves_per_level, &self.path, game, fig, pos);
+
+            self.expected_positions.push(pos);
+            for movement in &self.path {
+                pos = game.try_move(fig, pos, *movement).unwrap_or(pos);
+                self.expected_positions.push(pos);
+            }
+
+            if self.rng.random::<f32>() < self.difficulty.misdrop_chance {
+                // Shift the figure as it lands
+                let level = target.y();
+                let shift = if self.rng.random::<bool>() {
+                    Movement::MoveLeft
+                } else {
+                    Movement::MoveRight
+                };
+                self.moves_per_level.push((level, shift));
+                if let Some(shifted) = game.try_move(fig, target, shift) {
+                    self.expected_positions.push(shifted);
+                }
+            }
         }
     }
 
-    pub fn act_on_game(&mut self, game: &mut Game, ticks: u64) {
-        if self.last_figure != *game.current_figure() {
-            // Figure has changed since last call
-            let current_figure = game.current_figure().clone();
-            if let Some((ref fig, pos)) = current_figure {
-                if self.last_figure.is_none() {
-                    // Test if new figure
-                    self.new_figure_event(ticks, game.playfield(), fig, pos);
-                    self.figure_move_event(game, ticks, fig, pos);
-                } else {
-                    self.figure_move_event(game, ticks, fig, pos);
+    //
+    // Test if the figure has left the planned path, e.g. by a move being
+    // rejected, garbage pushing it up or someone else moving it, or if the
+    // playfield has changed under it. Moves are made as the figure falls
+    // so it may be anywhere below a position on the path, and anywhere
+    // above where the path starts.
+    //
+    fn off_plan(&self, game: &Game, pos: Position) -> bool {
+        let start = match self.expected_positions.first() {
+            Some(start) => *start,
+            // Nothing planned, nothing to leave
+            None => return false,
+        };
+        let on_path = self.expected_positions.iter().any(|expected| {
+            expected.x() == pos.x()
+                && expected.dir() == pos.dir()
+                && (expected.y() <= pos.y() || *expected == start)
+        });
+        let same_pf = self
+            .plan_pf
+            .as_ref()
+            .is_some_and(|plan_pf| same_blocks(plan_pf, game.playfield()));
+        !on_path || !same_pf
+    }
+
+    //
+    // Best placing of the current figure and the path to it from where
+    // the figure is, without making any moves. The placings are only rated
+    // again when the figure or the playfield has changed since the last
+    // call, the path when the figure has moved.
+    //
+    pub fn hint(&mut self, game: &Game) -> Option<&Hint> {
+        let current_figure = game.current_figure().clone();
+        let (fig, pos) = match current_figure {
+            Some(ref current) => current,
+            None => {
+                self.hint_figure = None;
+                return None;
+            }
+        };
+        let pf = game.playfield();
+        let same_pf = game.figures_placed() == self.hint_figures_placed
+            && self
+                .hint_pf
+                .as_ref()
+                .is_some_and(|hint_pf| same_blocks(hint_pf, pf));
+        if same_pf && self.hint_figure == current_figure {
+            return self.hint.as_ref();
+        }
+        let same_figure = self
+            .hint_figure
+            .as_ref()
+            .is_some_and(|(hint_fig, _)| hint_fig == fig);
+        if !same_pf || !same_figure {
+            self.rate_placings(game, fig, *pos);
+            self.hint_placings.clone_from(&self.eval_placing);
+            self.hint_pf.get_or_insert_with(|| pf.clone()).copy(pf);
+            self.hint_figures_placed = game.figures_placed();
+        }
+        let placings = std::mem::take(&mut self.hint_placings);
+        self.hint = self
+            .find_target_path(game, fig, *pos, &placings)
+            .map(|target| Hint {
+                pos: target,
+                path: self.path.clone(),
+            });
+        self.hint_placings = placings;
+        self.hint_figure = current_figure;
+        self.hint.as_ref()
+    }
+
+    // Best evaluation of the next figure on the playfield left after
+    // placing the current figure
+    fn next_figure_eval(
+        &mut self,
+        game: &Game,
+        fig: &Figure,
+        pos: Position,
+        next_fig: &Figure,
+    ) -> f32 {
+        let pf = game.playfield();
+        let lookahead_pf = self.lookahead_pf.get_or_insert_with(|| pf.clone());
+        lookahead_pf.copy(pf);
+        place_figure(lookahead_pf, fig, pos);
+
+        find_reachable_placings(&mut self.next_placings, game, lookahead_pf, next_fig);
+        self.com_type
+            .init_eval(lookahead_pf, self.next_placings.len());
+        let mut best_eval = None;
+        for next_pos in &self.next_placings {
+            let eval = self
+                .com_type
+                .eval_placing(lookahead_pf, next_fig, *next_pos);
+            best_eval = Some(best_eval.map_or(eval, |best: f32| best.max(eval)));
+        }
+        best_eval.unwrap_or(NO_PLACING_EVAL)
+    }
+
+    //
+    // Every placing of the current figure, best first, with the terms of
+    // its evaluation. What the lookahead or beam search adds to the
+    // evaluation of the placing itself is a term of its own.
+    //
+    pub fn explain(&mut self, game: &Game) -> Vec<Explanation> {
+        let (fig, pos) = match game.current_figure() {
+            Some(current) => current.clone(),
+            None => return Vec::new(),
+        };
+        self.rate_placings(game, &fig, pos);
+        let pf = game.playfield();
+        let search_term = if self.beam_search.is_some() {
+            "beam_search"
+        } else {
+            "lookahead"
+        };
+        let com_type = &mut self.com_type;
+        com_type.init_eval(pf, self.eval_placing.len());
+        let mut explanations = Vec::new();
+        for placing in &self.eval_placing {
+            let mut terms = com_type.explain_placing(pf, &fig, placing.pos);
+            let own_eval: f32 = terms.iter().map(|(_, value)| value).sum();
+            if placing.eval != own_eval {
+                terms.push((search_term, placing.eval - own_eval));
+            }
+            explanations.push(Explanation {
+                pos: placing.pos,
+                eval: placing.eval,
+                terms,
+            });
+        }
+        explanations
+    }
+
+    fn update_frames_per_figure(&mut self, frame: u64) {
+        let frames = frame.saturating_sub(self.last_figure_frame) as f32;
+        self.last_figure_frame = frame;
+        self.frames_per_figure = if self.frames_per_figure == 0.0 {
+            frames
+        } else {
+            // Moving average over the last few figures
+            self.frames_per_figure * 0.8 + frames * 0.2
+        };
+    }
+
+    // Plan for the current figure from scratch on the next call, e.g.
+    // after the game has been rewound
+    pub fn forget_figure(&mut self) {
+        self.last_figure = None;
+        self.moves_per_level.clear();
+    }
+
+    pub fn act_on_game(&mut self, game: &mut Game, frame: u64) {
+        let current_figure = game.current_figure().clone();
+        let (fig, pos) = match current_figure {
+            Some(ref current) => current,
+            None => {
+                self.last_figure = None;
+                return;
+            }
+        };
+        let same_figure = match self.last_figure {
+            Some((ref last_fig, _)) => last_fig == fig,
+            None => false,
+        };
+        if !same_figure || game.figures_placed() != self.figures_placed {
+            // New figure (or one swapped in from hold)
+            self.figures_placed = game.figures_placed();
+            self.update_frames_per_figure(frame);
+            let pressure = game.garbage_rise_rate() * self.frames_per_figure;
+            self.com_type.set_garbage_pressure(pressure);
+            let think_levels = (game.gravity() * self.difficulty.think_time as f32) as u32;
+            self.plan(game, fig, *pos, think_levels);
+            self.think_until = frame + self.difficulty.think_time;
+            self.next_move_frame = self.think_until;
+        } else if self.off_plan(game, *pos) {
+            // Plan again from where the figure is. Moves still queued were
+            // planned for another position.
+            game.clear_moves();
+            self.plan(game, fig, *pos, 0);
+            self.next_move_frame = self.think_until.max(frame);
+        }
+        self.make_moves(game, frame, *pos);
+        self.last_figure = current_figure;
+    }
+}
+
+// Place a figure and throw away the lines it fills. Returns the number of
+// lines thrown away.
+pub fn place_figure(pf: &mut Playfield, fig: &Figure, pos: Position) -> u32 {
+    fig.place(pf, pos);
+    let mut full_lines = pf.locked_lines();
+    full_lines.sort();
+    for line in &full_lines {
+        pf.throw_line(*line);
+    }
+    full_lines.len() as u32
+}
+
+fn same_blocks(pf: &Playfield, other: &Playfield) -> bool {
+    pf.blocks().row_iter().eq(other.blocks().row_iter())
+}
+
+//
+// Find placings a figure not yet in play can reach from where it spawns.
+// How fast it will fall isn't known yet, so it is assumed to have time to
+// move as far as it fits on every level.
+//
+pub fn find_reachable_placings(
+    placings: &mut Vec<Position>,
+    game: &Game,
+    pf: &Playfield,
+    fig: &Figure,
+) {
+    const MOVES: [Movement; 5] = [
+        Movement::MoveDown,
+        Movement::MoveLeft,
+        Movement::MoveRight,
+        Movement::RotateCW,
+        Movement::RotateCCW,
+    ];
+    placings.clear();
+    find_placement(placings, pf, fig);
+
+    let spawn_pos = game.spawn_position(fig);
+    let mut visited = vec![false; search_cells(pf)];
+    let mut queue = vec![spawn_pos];
+    if let Some(i) = search_cell(pf, spawn_pos) {
+        visited[i] = true;
+    }
+    let mut index = 0;
+    while index < queue.len() {
+        let pos = queue[index];
+        for movement in &MOVES {
+            if let Some(next_pos) = game.try_move_on(pf, fig, pos, *movement) {
+                if let Some(i) = search_cell(pf, next_pos) {
+                    if !visited[i] {
+                        visited[i] = true;
+                        queue.push(next_pos);
+                    }
                 }
             }
-            self.last_figure = current_figure;
         }
+        index += 1;
     }
+    placings.retain(|placing| search_cell(pf, *placing).is_some_and(|i| visited[i]));
 }
 
-fn path_to_moves_per_level(moves: &mut Vec<(i32, Movement)>, path: &[Movement]) {
+// Big figures only reach every other column
+fn retain_reachable(placings: &mut Vec<Position>, game: &Game, start_pos: Position) {
+    let step = game.move_step();
+    if step > 1 {
+        placings.retain(|placing| (placing.x() - start_pos.x()) % step == 0);
+    }
+}
+
+// Follow the path to find the level each move is made at. Rotations may
+// move the figure up or down as well.
+fn path_to_moves_per_level(
+    moves: &mut Vec<(i32, Movement)>,
+    path: &[Movement],
+    game: &Game,
+    fig: &Figure,
+    start_pos: Position,
+) {
     moves.clear();
-    let mut level: i32 = 0;
+    let mut pos = start_pos;
     for movement in path {
-        if *movement == Movement::MoveDown {
-            level += 1;
+        if *movement != Movement::MoveDown {
+            moves.push((pos.y(), *movement));
+        }
+        pos = game.try_move(fig, pos, *movement).unwrap_or(pos);
+    }
+}
+
+// Drop figure from position until it hits the ground
+fn drop_position(game: &Game, fig: &Figure, mut pos: Position) -> Position {
+    while let Some(below) = game.try_move(fig, pos, Movement::MoveDown) {
+        pos = below;
+    }
+    pos
+}
+
+//
+// Find a path for a figure that falls to the ground between every move
+// (20G). The resulting path is in reverse order, same as for FindPath.
+//
+fn find_grounded_path(
+    path: &mut Vec<Movement>,
+    game: &Game,
+    fig: &Figure,
+    start_pos: Position,
+    end_pos: Position,
+) {
+    const MOVES: [Movement; 4] = [
+        Movement::MoveLeft,
+        Movement::MoveRight,
+        Movement::RotateCW,
+        Movement::RotateCCW,
+    ];
+    path.clear();
+    let start_pos = drop_position(game, fig, start_pos);
+    let mut visited: Vec<(Position, Option<(usize, Movement)>)> = vec![(start_pos, None)];
+    let mut index = 0;
+    while index < visited.len() {
+        let pos = visited[index].0;
+        if pos == end_pos {
+            // Walk back to the start to get the path
+            let mut step = visited[index].1;
+            while let Some((prev_index, movement)) = step {
+                path.push(movement);
+                step = visited[prev_index].1;
+            }
+            return;
+        }
+        for movement in &MOVES {
+            let test_pos = match game.try_move(fig, pos, *movement) {
+                Some(test_pos) => test_pos,
+                None => continue,
+            };
+            let next_pos = drop_position(game, fig, test_pos);
+            if !visited.iter().any(|(p, _)| *p == next_pos) {
+                visited.push((next_pos, Some((index, *movement))));
+            }
+        }
+        index += 1;
+    }
+}
+
+// Figures can reach a few blocks outside of the playfield with the empty
+// parts of their faces
+const SEARCH_MARGIN: i32 = 8;
+
+// Number of positions (with direction) a search on the playfield can visit
+fn search_cells(pf: &Playfield) -> usize {
+    let width = pf.width() as usize + 2 * SEARCH_MARGIN as usize;
+    let height = pf.height() as usize + 2 * SEARCH_MARGIN as usize;
+    width * height * 4
+}
+
+// Index of a position among the ones a search can visit
+fn search_cell(pf: &Playfield, pos: Position) -> Option<usize> {
+    let width = pf.width() as i32 + 2 * SEARCH_MARGIN;
+    let height = pf.height() as i32 + 2 * SEARCH_MARGIN;
+    let x = pos.x() + SEARCH_MARGIN;
+    let y = pos.y() + SEARCH_MARGIN;
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return None;
+    }
+    Some(((y * width + x) * 4 + pos.dir().rem_euclid(4)) as usize)
+}
+
+// Position reached in a path search, the number of moves made on its level
+// (or levels left to fall before the next move) and the step (and move) it
+// was reached from
+#[derive(Clone, Copy)]
+struct SearchStep {
+    pos: Position,
+    moves: usize,
+    prev: Option<(usize, Movement)>,
+}
+
+//
+// Find a path for a figure allowed a number of moves on each level before
+// it falls to the next, or falling a number of levels after each move,
+// moving it by the rules of the game. The resulting path is in reverse
+// order, same as for FindPath.
+//
+fn find_game_path(
+    path: &mut Vec<Movement>,
+    game: &Game,
+    fig: &Figure,
+    start_pos: Position,
+    end_pos: Position,
+    moves_per_level: usize,
+    levels_per_move: usize,
+) {
+    const MOVES: [Movement; 5] = [
+        Movement::MoveDown,
+        Movement::MoveLeft,
+        Movement::MoveRight,
+        Movement::RotateCW,
+        Movement::RotateCCW,
+    ];
+    let counts = moves_per_level.max(levels_per_move);
+    let pf = game.playfield();
+    let visited_index =
+        |pos: Position, moves: usize| search_cell(pf, pos).map(|cell| cell * (counts + 1) + moves);
+
+    path.clear();
+    let mut visited = vec![false; search_cells(pf) * (counts + 1)];
+    let mut queue = vec![SearchStep {
+        pos: start_pos,
+        moves: 0,
+        prev: None,
+    }];
+    let mut index = 0;
+    while index < queue.len() {
+        let SearchStep { pos, moves, .. } = queue[index];
+        let grounded = game.try_move(fig, pos, Movement::MoveDown).is_none();
+        if pos == end_pos {
+            // Walk back to the start to get the path
+            let mut step = queue[index].prev;
+            while let Some((prev_index, movement)) = step {
+                path.push(movement);
+                step = queue[prev_index].prev;
+            }
+            return;
+        }
+        for movement in &MOVES {
+            let next_moves = if levels_per_move > 1 {
+                // Counting levels left to fall before the next move, which
+                // on the ground can be made once the move interval is up
+                if *movement == Movement::MoveDown {
+                    moves.saturating_sub(1)
+                } else if moves == 0 || grounded {
+                    levels_per_move
+                } else {
+                    continue;
+                }
+            } else if *movement == Movement::MoveDown {
+                0
+            } else if moves < moves_per_level {
+                moves + 1
+            } else {
+                continue;
+            };
+            let next_pos = match game.try_move(fig, pos, *movement) {
+                Some(next_pos) => next_pos,
+                None => continue,
+            };
+            if let Some(i) = visited_index(next_pos, next_moves) {
+                if !visited[i] {
+                    visited[i] = true;
+                    queue.push(SearchStep {
+                        pos: next_pos,
+                        moves: next_moves,
+                        prev: Some((index, *movement)),
+                    });
+                }
+            }
+        }
+        index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_playfield::*;
+    use crate::game::GameConfig;
+    use crate::jitter_computer::JitterComputer;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn hint() {
+        let pf = "......\n......\n......\n......\n1111..\n1111..\n";
+        let mut game = sequence_game(pf, vec![figure(O); 2], quick_config());
+        game.update(0);
+        let (fig, start_pos) = game.current_figure().clone().unwrap();
+
+        // Best rated placing and a path that leads there
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+        let hint = player.hint(&game).unwrap().clone();
+        assert_eq!(hint.pos, player.eval_placing[0].pos);
+        let end_pos = hint.path.iter().fold(start_pos, |pos, movement| {
+            game.try_move(&fig, pos, *movement).unwrap()
+        });
+        assert_eq!(end_pos, hint.pos);
+
+        // Nothing is moved by the hint
+        game.update(1);
+        let (_, pos) = game.current_figure().clone().unwrap();
+        assert_eq!(pos.x(), start_pos.x());
+        assert_eq!(player.hint(&game), Some(&hint));
+    }
+
+    // Counts the evaluations started, to tell when placings are rated
+    struct CountInits(JitterComputer, u32);
+
+    impl ComputerType for CountInits {
+        fn init_eval(&mut self, pf: &Playfield, avail_placings: usize) {
+            self.1 += 1;
+            self.0.init_eval(pf, avail_placings);
+        }
+
+        fn eval_placing(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> f32 {
+            self.0.eval_placing(pf, fig, pos)
+        }
+    }
+
+    #[test]
+    fn hint_cache() {
+        let config = GameConfig {
+            gravity: 1.0,
+            ..quick_config()
+        };
+        let mut game = sequence_game(&"......\n".repeat(8), vec![figure(O); 3], config);
+        let mut player = ComputerPlayer::new(2.0, CountInits(JitterComputer::new(), 0));
+        player.set_lookahead(false);
+
+        // Only the path is worked out again as the figure falls
+        game.update(0);
+        let target = player.hint(&game).unwrap().pos;
+        assert_eq!(player.com_type.1, 1);
+        game.update(1);
+        let hint = player.hint(&game).unwrap().clone();
+        assert_eq!(player.com_type.1, 1);
+        assert_eq!(hint.pos, target);
+        let (fig, pos) = game.current_figure().clone().unwrap();
+        let end_pos = hint.path.iter().fold(pos, |pos, movement| {
+            game.try_move(&fig, pos, *movement).unwrap()
+        });
+        assert_eq!(end_pos, target);
+
+        // Rated again for the next figure
+        let mut frame = 2;
+        while game.figures_placed() == 0 && frame < 1000 {
+            game.update(frame);
+            frame += 1;
+        }
+        game.update(frame);
+        let target = player.hint(&game).unwrap().pos;
+        assert_eq!(player.com_type.1, 2);
+
+        // And when garbage is pushed up under the same figure
+        game.push_garbage_row(0);
+        let pos = player.hint(&game).unwrap().pos;
+        assert_eq!(player.com_type.1, 3);
+        assert_eq!(pos.y(), target.y() - 1);
+    }
+
+    // Play the first figure at the given gravity and check that it is
+    // steered to the hinted placing, which is returned
+    fn steer_to_hint(ascii: &str, gravity: f32) -> Position {
+        let config = GameConfig {
+            gravity,
+            ..quick_config()
+        };
+        let mut game = sequence_game(ascii, crate::init_figures(), config);
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+        player.set_lookahead(false);
+
+        let mut frame = 0;
+        game.update(frame);
+        let (fig, pos) = game.current_figure().clone().unwrap();
+        let target = player.hint(&game).unwrap().pos;
+        assert_ne!(target.x(), pos.x());
+        while game.figures_placed() == 0 && frame < 1000 {
+            player.act_on_game(&mut game, frame);
+            frame += 1;
+            game.update(frame);
+        }
+
+        let mut expected = playfield_from_ascii(ascii).unwrap();
+        fig.place(&mut expected, target);
+        assert_eq!(
+            playfield_to_ascii(game.playfield()),
+            playfield_to_ascii(&expected)
+        );
+        target
+    }
+
+    #[test]
+    fn steer_under_gravity() {
+        // Moved along the stack to the well
+        let well = "..........\n".repeat(8) + &"111111111.\n".repeat(4);
+        steer_to_hint(&well, 2.0);
+        steer_to_hint(&well, 20.0);
+
+        // Only reached by moving over the wall before falling past it
+        let wall = "..............\n".repeat(8)
+            + &".........1....\n".repeat(2)
+            + &"1111111111....\n".repeat(10);
+        let target = steer_to_hint(&wall, 1.0);
+        assert!(target.x() > 9);
+    }
+
+    #[test]
+    fn big_figures() {
+        // Odd height, so big figures can't land on every other row only
+        let config = GameConfig {
+            big: true,
+            ..quick_config()
+        };
+        let mut game = sequence_game(&"........\n".repeat(15), vec![figure(O); 4], config);
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+
+        let mut frame = 0;
+        while game.figures_placed() < 3 && frame < 10000 {
+            player.act_on_game(&mut game, frame);
+            game.update(frame);
+            frame += 1;
+        }
+
+        // Two big figures make two lines, which leaves one on the floor
+        assert_eq!(game.lines_cleared(), 4);
+        let ascii = "........\n".repeat(11) + &"....4444\n".repeat(4);
+        let ascii_left = "........\n".repeat(11) + &"4444....\n".repeat(4);
+        let result = playfield_to_ascii(game.playfield());
+        assert!(result == ascii || result == ascii_left, "{}", result);
+    }
+
+    #[test]
+    fn lookahead() {
+        let ascii = "......\n".repeat(6) + "11....\n11....\n11..1.\n11..11\n";
+        let hint = |next: usize| {
+            let sequence = vec![figure(I), figure(next)];
+            let mut game = sequence_game(&ascii, sequence, quick_config());
+            game.update(0);
+            let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+            player.hint(&game).unwrap().pos
+        };
+
+        // Where the I goes depends on what comes after it
+        assert_ne!(hint(1), hint(O));
+    }
+
+    #[test]
+    fn reachable_placings() {
+        let o = figure(O);
+        let pf = "......\n......\n...111\n......\n......\n";
+        let game = sequence_game(pf, vec![o.clone()], GameConfig::default());
+        let mut placings = Vec::new();
+        find_reachable_placings(&mut placings, &game, game.playfield(), &o);
+
+        // Slid in under the overhang as well as dropped on top of it
+        assert!(placings.contains(&Position::new((4, 3, 0))));
+        assert!(placings.contains(&Position::new((3, 0, 0))));
+        assert!(placings.contains(&Position::new((0, 3, 0))));
+    }
+
+    #[test]
+    fn replan() {
+        let pf = "..........\n".repeat(12);
+        let mut game = sequence_game(&pf, crate::init_figures(), quick_config());
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+        player.set_lookahead(false);
+
+        // Where the figure would be placed if left alone
+        let mut frame = 0;
+        game.update(frame);
+        let (fig, pos) = game.current_figure().clone().unwrap();
+        let target = player.hint(&game).unwrap().pos;
+
+        // Pushed away from there as soon as the plan is made
+        let push = if target.x() < pos.x() {
+            Movement::MoveRight
         } else {
-            moves.push((level, *movement));
+            Movement::MoveLeft
+        };
+        player.act_on_game(&mut game, frame);
+        for _ in 0..4 {
+            game.add_move(push, frame);
+        }
+        while game.figures_placed() == 0 {
+            frame += 1;
+            player.act_on_game(&mut game, frame);
+            game.update(frame);
+        }
+
+        let mut expected = playfield_from_ascii(&pf).unwrap();
+        fig.place(&mut expected, target);
+        assert_eq!(
+            playfield_to_ascii(game.playfield()),
+            playfield_to_ascii(&expected)
+        );
+    }
+
+    #[test]
+    fn explain() {
+        let pf = "......\n......\n......\n......\n1111..\n11.1..\n";
+        let mut game = sequence_game(pf, crate::init_figures(), quick_config());
+        game.update(0);
+
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+        let explanations = player.explain(&game);
+        assert_eq!(explanations.len(), player.avail_placings.len());
+        for (explanation, next) in explanations.iter().zip(explanations.iter().skip(1)) {
+            assert!(explanation.eval >= next.eval);
+        }
+        for explanation in &explanations {
+            let sum: f32 = explanation.terms.iter().map(|(_, value)| value).sum();
+            assert!((sum - explanation.eval).abs() < 0.001);
+            assert_eq!(explanation.terms[0].0, "depth");
         }
+        // The next figure is taken into account
+        assert!(explanations
+            .iter()
+            .any(|explanation| explanation.terms.iter().any(|term| term.0 == "lookahead")));
     }
 }
@@ -0,0 +1,107 @@
+use crate::computer_player::*;
+use crate::jitter_computer::*;
+use crate::rollout_computer::*;
+use crate::weighted_computer::*;
+
+// Names of the computer types that can be created by name
+pub const COMPUTER_TYPES: [&str; 5] = ["jitter", "dellacherie", "el-tetris", "weighted", "rollout"];
+
+//
+// Create a computer type by name. Parameters are given as text, one
+// 'name value' per line:
+//
+//   jitter        no parameters
+//   dellacherie   no parameters, Dellacherie's weights
+//   el-tetris     no parameters, El-Tetris weights
+//   weighted      the weight of each feature, e.g. 'holes -4'
+//   rollout       'rollouts' per placing (default 4), 'depth' in figures
+//                 (default 3) and 'seed' of the random figures (default 0)
+//
+pub fn new_computer_type(name: &str, params: &str) -> Result<Box<dyn ComputerType>, String> {
+    let no_params = || {
+        if params.trim().is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Computer type '{}' takes no parameters", name))
+        }
+    };
+    match name {
+        "jitter" => {
+            no_params()?;
+            Ok(Box::new(JitterComputer::new()))
+        }
+        "dellacherie" => {
+            no_params()?;
+            Ok(Box::new(WeightedComputer::dellacherie()))
+        }
+        "el-tetris" => {
+            no_params()?;
+            Ok(Box::new(WeightedComputer::el_tetris()))
+        }
+        "weighted" => {
+            let weights = Features::from_text(params)?;
+            Ok(Box::new(WeightedComputer::new(weights)))
+        }
+        "rollout" => {
+            let mut values = [("rollouts", 4), ("depth", 3), ("seed", 0)];
+            parse_params(name, params, &mut values)?;
+            let [rollouts, depth, seed] = values.map(|(_, value)| value);
+            Ok(Box::new(RolloutComputer::new(
+                crate::init_figures(),
+                rollouts as usize,
+                depth as usize,
+                seed,
+            )))
+        }
+        _ => Err(format!(
+            "Unknown computer type '{}', expected one of: {}",
+            name,
+            COMPUTER_TYPES.join(", ")
+        )),
+    }
+}
+
+// Parse 'name value' lines into the given values, which keep their
+// defaults when left out
+fn parse_params(type_name: &str, text: &str, values: &mut [(&str, u64)]) -> Result<(), String> {
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let value = match values.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, value)) => value,
+            None => {
+                return Err(format!(
+                    "Unknown parameter '{}' for computer type '{}'",
+                    name, type_name
+                ))
+            }
+        };
+        *value = match (words.next().map(str::parse::<u64>), words.next()) {
+            (Some(Ok(parsed)), None) => parsed,
+            _ => return Err(format!("Expected a single whole number for '{}'", name)),
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computer_types() {
+        for name in &COMPUTER_TYPES {
+            assert!(new_computer_type(name, "").is_ok());
+        }
+        assert!(new_computer_type("weighted", "holes -4\nwells -1").is_ok());
+        assert!(new_computer_type("weighted", "height 1").is_err());
+        assert!(new_computer_type("jitter", "holes -4").is_err());
+        assert!(new_computer_type("rollout", "rollouts 8\ndepth 2").is_ok());
+        assert!(new_computer_type("rollout", "rollouts -1").is_err());
+        assert!(new_computer_type("rollout", "width 2").is_err());
+        assert!(new_computer_type("random", "").is_err());
+    }
+}
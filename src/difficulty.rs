@@ -0,0 +1,117 @@
+use rand::rngs::SmallRng;
+use rand::RngExt;
+use wasm_bindgen::prelude::*;
+
+// Preset skill levels of the computer player
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AiDifficulty {
+    Easy,
+    Medium,
+    Hard,
+    // Always the best placing by its own evaluation, as fast as it can
+    Perfect,
+}
+
+impl AiDifficulty {
+    pub fn settings(self) -> Difficulty {
+        match self {
+            AiDifficulty::Easy => Difficulty {
+                think_time: 12,
+                move_delay: 1,
+                temperature: 1.0,
+                misdrop_chance: 0.06,
+            },
+            AiDifficulty::Medium => Difficulty {
+                think_time: 8,
+                move_delay: 0,
+                temperature: 0.5,
+                misdrop_chance: 0.03,
+            },
+            AiDifficulty::Hard => Difficulty {
+                think_time: 4,
+                move_delay: 0,
+                temperature: 0.1,
+                misdrop_chance: 0.01,
+            },
+            AiDifficulty::Perfect => Difficulty::default(),
+        }
+    }
+}
+
+// How humanly the computer player plays. Times are in frames
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Difficulty {
+    // Time from a figure spawning until the first move
+    pub think_time: u64,
+    // Extra time between moves
+    pub move_delay: u64,
+    // Temperature of the softmax over placing evaluations used to choose
+    // a placing. The higher the more likely a worse placing is chosen, at
+    // 0 the best one always is.
+    pub temperature: f32,
+    // Chance of shifting a figure one step too far just as it lands
+    pub misdrop_chance: f32,
+}
+
+// Pick one of the evaluations (best first) by softmax
+pub fn softmax_pick(evals: &[f32], temperature: f32, rng: &mut SmallRng) -> usize {
+    if temperature <= 0.0 || evals.len() < 2 {
+        return 0;
+    }
+    let best = evals[0];
+    let weights: Vec<f32> = evals
+        .iter()
+        .map(|eval| ((eval - best) / temperature).exp())
+        .collect();
+    let mut pick = rng.random::<f32>() * weights.iter().sum::<f32>();
+    for (index, weight) in weights.iter().enumerate() {
+        if pick < *weight {
+            return index;
+        }
+        pick -= weight;
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer_player::ComputerPlayer;
+    use crate::headless::*;
+    use crate::jitter_computer::JitterComputer;
+    use rand::SeedableRng;
+
+    #[test]
+    fn softmax() {
+        let evals = [3.0, 2.0, -10.0];
+        let mut rng = SmallRng::seed_from_u64(1);
+        let picks: Vec<usize> = (0..1000)
+            .map(|_| softmax_pick(&evals, 1.0, &mut rng))
+            .collect();
+        let count = |index| picks.iter().filter(|pick| **pick == index).count();
+
+        // e^0 : e^-1 : e^-13, so about 73% / 27% / 0%
+        assert!((650..800).contains(&count(0)));
+        assert!((200..350).contains(&count(1)));
+        assert_eq!(count(2), 0);
+
+        // Cold enough to always pick the best
+        assert!((0..100).all(|_| softmax_pick(&evals, 0.0, &mut rng) == 0));
+    }
+
+    #[test]
+    fn seeded_play() {
+        let play = |seed| {
+            let mut game = new_game(10, 20, headless_config(), 7);
+            let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+            player.set_difficulty(AiDifficulty::Easy.settings(), seed);
+            play_game(&mut game, &mut player, 30);
+            crate::ascii_playfield::playfield_to_ascii(game.playfield())
+        };
+
+        // Same mistakes for the same seed
+        assert_eq!(play(1), play(1));
+        assert_ne!(play(1), play(2));
+    }
+}
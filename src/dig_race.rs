@@ -0,0 +1,88 @@
+use rstris::figure::Figure;
+use rstris::playfield::Playfield;
+
+use crate::game::*;
+use crate::game_mode::*;
+
+//
+// Race to dig through rows of messy garbage, each row with a hole at a
+// random column.
+//
+pub struct DigRace {
+    garbage_rows: u32,
+}
+
+impl DigRace {
+    pub fn new(garbage_rows: u32) -> Self {
+        DigRace { garbage_rows }
+    }
+
+    // Create a game with the garbage rows in place. Figures and holes are
+    // the same for the same seed.
+    pub fn start(
+        &self,
+        pf: Playfield,
+        available_figures: Vec<Figure>,
+        config: GameConfig,
+        seed: u64,
+    ) -> Game {
+        let mut game = Game::new_seeded(pf, available_figures, config, seed);
+        for _ in 0..self.garbage_rows {
+            game.push_random_garbage_row();
+        }
+        game
+    }
+}
+
+impl GameMode for DigRace {
+    fn status(&self, game: &Game) -> ModeStatus {
+        if game.garbage_rows() == 0 {
+            ModeStatus::Completed
+        } else if game.is_game_over() {
+            ModeStatus::Failed
+        } else {
+            ModeStatus::InProgress
+        }
+    }
+
+    fn allows_undo(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_playfield::*;
+    use crate::computer_player::ComputerPlayer;
+    use crate::jitter_computer::JitterComputer;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn status() {
+        let dig_race = DigRace::new(1);
+        let config = quick_config();
+        let start = |seed| {
+            let pf = Playfield::new("Playfield", 6, 8);
+            dig_race.start(pf, crate::init_figures(), config.clone(), seed)
+        };
+        let mut game = start(1);
+        assert_eq!(
+            playfield_to_ascii(game.playfield()),
+            playfield_to_ascii(start(1).playfield())
+        );
+        assert_eq!(dig_race.status(&game), ModeStatus::InProgress);
+
+        // Completed as soon as the last garbage row is cleared
+        let mut player = ComputerPlayer::new(2.0, JitterComputer::new());
+        let mut frame = 0;
+        while game.garbage_rows() > 0 && frame < 10000 {
+            assert_eq!(dig_race.status(&game), ModeStatus::InProgress);
+            player.act_on_game(&mut game, frame);
+            game.update(frame);
+            frame += 1;
+        }
+        assert_eq!(game.garbage_rows(), 0);
+        assert_eq!(dig_race.status(&game), ModeStatus::Completed);
+    }
+}
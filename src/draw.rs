@@ -1,57 +1,43 @@
-use js_sys::WebAssembly;
 use wasm_bindgen::JsCast;
 use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlShader};
 
 use nalgebra::Matrix4;
 use nalgebra_glm as glm;
 
+type Color = (f32, f32, f32, f32);
+
 struct GLBuf {
     buf_ref: web_sys::WebGlBuffer,
-    js_mem_buf: js_sys::Float32Array,
     data_len: u32,
 }
 
 impl GLBuf {
     fn new(gl: &WebGlRenderingContext, data: &[f32]) -> Self {
-        let mem_buf = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-
-        let js_mem_buf = js_sys::Float32Array::new(&mem_buf);
-
-        let location = data.as_ptr() as u32 / 4;
-        let data_array = js_mem_buf.subarray(location, location + data.len() as u32);
         let buf_ref = gl
             .create_buffer()
             .ok_or("failed to create color buffer")
             .unwrap();
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&buf_ref));
-        gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            &data_array,
-            WebGlRenderingContext::STATIC_DRAW,
-        );
-
-        GLBuf {
+        let mut buf = GLBuf {
             buf_ref,
-            js_mem_buf,
-            data_len: data.len() as u32,
-        }
+            data_len: 0,
+        };
+        buf.update(gl, data);
+        buf
     }
 
-    fn update(&self, gl: &WebGlRenderingContext, data: &[f32]) {
-        let location = data.as_ptr() as u32 / 4;
-        let data_array = self
-            .js_mem_buf
-            .subarray(location, location + data.len() as u32);
-
+    fn update(&mut self, gl: &WebGlRenderingContext, data: &[f32]) {
         gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.buf_ref));
-        gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            &data_array,
-            WebGlRenderingContext::STATIC_DRAW,
-        );
+        // The wasm memory moves when it grows, so view it anew for every
+        // upload. Nothing may allocate until the data has been copied.
+        unsafe {
+            let data_array = js_sys::Float32Array::view(data);
+            gl.buffer_data_with_array_buffer_view(
+                WebGlRenderingContext::ARRAY_BUFFER,
+                &data_array,
+                WebGlRenderingContext::STATIC_DRAW,
+            );
+        }
+        self.data_len = data.len() as u32;
     }
 }
 
@@ -63,6 +49,7 @@ pub struct Draw {
 
     // shader references
     projection_matrix: web_sys::WebGlUniformLocation,
+    background_color: web_sys::WebGlUniformLocation,
     position_vertex: u32,
     vertex_color: u32,
 
@@ -72,8 +59,16 @@ pub struct Draw {
     blocks: Vec<f32>,
     dirty: bool,
 
+    // Color blocks are blended with according to their alpha
+    background: (f32, f32, f32),
+
     block_buf: GLBuf,
     color_buf: GLBuf,
+
+    // Blocks outlined on top of the others, with their colors
+    outlines: Vec<(u32, u32, Color)>,
+    outline_buf: GLBuf,
+    outline_color_buf: GLBuf,
 }
 
 impl Draw {
@@ -99,12 +94,14 @@ impl Draw {
             attribute vec4 a_vertex_color;
             attribute vec2 a_position;
             uniform mat4 u_matrix;
+            uniform vec3 u_background;
 
             varying lowp vec4 vColor;
 
             void main() {
                 gl_Position = vec4((u_matrix * vec4(a_position, 1, 1)).xy, 0, 1);
-                vColor = a_vertex_color + gl_Position * 0.1;
+                vColor = vec4(mix(u_background, a_vertex_color.rgb, a_vertex_color.a), 1)
+                    + gl_Position * 0.1;
             }
             "#,
         )
@@ -127,6 +124,9 @@ impl Draw {
         let projection_matrix = gl
             .get_uniform_location(&program, "u_matrix")
             .expect("failed to get projection matrix");
+        let background_color = gl
+            .get_uniform_location(&program, "u_background")
+            .expect("failed to get background color");
         let position_vertex = gl.get_attrib_location(&program, "a_position") as u32;
         let vertex_color = gl.get_attrib_location(&program, "a_vertex_color") as u32;
 
@@ -152,13 +152,28 @@ impl Draw {
             canvas,
             block_buf: GLBuf::new(&gl, &block_vertex),
             color_buf: GLBuf::new(&gl, &blocks),
+            outlines: Vec::new(),
+            outline_buf: GLBuf::new(&gl, &[]),
+            outline_color_buf: GLBuf::new(&gl, &[]),
             gl,
             program,
             projection_matrix,
+            background_color,
             position_vertex,
             vertex_color,
             dirty: true,
             blocks,
+            background: (0.0, 0.0, 0.0),
+        }
+    }
+
+    fn block_size(canvas: &web_sys::HtmlCanvasElement, block_cols: u32, block_rows: u32) -> f32 {
+        let block_size_w = canvas.client_width() as f32 / block_cols as f32;
+        let block_size_h = canvas.client_height() as f32 / block_rows as f32;
+        if block_size_w > block_size_h {
+            block_size_h
+        } else {
+            block_size_w
         }
     }
 
@@ -168,13 +183,7 @@ impl Draw {
         block_rows: u32,
     ) -> Vec<f32> {
         let space = 1.00;
-        let block_size_w = canvas.client_width() as f32 / block_cols as f32;
-        let block_size_h = canvas.client_height() as f32 / block_rows as f32;
-        let size = if block_size_w > block_size_h {
-            block_size_h
-        } else {
-            block_size_w
-        };
+        let size = Self::block_size(canvas, block_cols, block_rows);
 
         let start_x = 0.0;
         let start_y = 0.0;
@@ -235,6 +244,50 @@ impl Draw {
         }
     }
 
+    // Outline a block, drawn on top of it until the outlines are cleared
+    pub fn add_outline(&mut self, x: u32, y: u32, color: Color) {
+        if x < self.block_cols && y < self.block_rows {
+            self.outlines.push((x, y, color));
+        }
+    }
+
+    pub fn clear_outlines(&mut self) {
+        self.outlines.clear();
+    }
+
+    // Vertices of a frame along the edges of each outlined block, and
+    // their colors
+    fn generate_outlines(&self) -> (Vec<f32>, Vec<f32>) {
+        let size = Self::block_size(&self.canvas, self.block_cols, self.block_rows);
+        let width = (size / 8.0).max(1.0);
+        let mut vertices = vec![];
+        let mut colors = vec![];
+        for (x, y, color) in &self.outlines {
+            let left = *x as f32 * size;
+            let bottom = *y as f32 * size;
+            let right = left + size;
+            let top = bottom + size;
+            let sides = [
+                (left, bottom, right, bottom + width),
+                (left, top - width, right, top),
+                (left, bottom, left + width, top),
+                (right - width, bottom, right, top),
+            ];
+            for (l, b, r, t) in &sides {
+                vertices.extend_from_slice(&[*l, *b, *r, *b, *l, *t, *r, *b, *r, *t, *l, *t]);
+                for _ in 0..6 {
+                    colors.extend_from_slice(&[color.0, color.1, color.2, color.3]);
+                }
+            }
+        }
+        (vertices, colors)
+    }
+
+    // Set the color seen through blocks that are not fully opaque
+    pub fn set_background(&mut self, color: (f32, f32, f32)) {
+        self.background = color;
+    }
+
     pub fn draw_blocks(&mut self) {
         if self.last_canvas_size.0 != self.canvas.client_width()
             || self.last_canvas_size.1 != self.canvas.client_height()
@@ -253,11 +306,31 @@ impl Draw {
         self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
         self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
 
+        self.gl.use_program(Some(&self.program));
+        self.set_projection();
+        self.gl.uniform3f(
+            Some(&self.background_color),
+            self.background.0,
+            self.background.1,
+            self.background.2,
+        );
+        self.draw_triangles(&self.block_buf, &self.color_buf);
+
+        if !self.outlines.is_empty() {
+            let (vertices, colors) = self.generate_outlines();
+            self.outline_buf.update(&self.gl, &vertices);
+            self.outline_color_buf.update(&self.gl, &colors);
+            self.draw_triangles(&self.outline_buf, &self.outline_color_buf);
+        }
+    }
+
+    // Draw triangles with two coordinates and a color per vertex
+    fn draw_triangles(&self, vertex_buf: &GLBuf, color_buf: &GLBuf) {
         {
             // Bind the vertex buffer
             self.gl.bind_buffer(
                 WebGlRenderingContext::ARRAY_BUFFER,
-                Some(&self.block_buf.buf_ref),
+                Some(&vertex_buf.buf_ref),
             );
             self.gl.vertex_attrib_pointer_with_i32(
                 self.position_vertex,
@@ -273,7 +346,7 @@ impl Draw {
             // Bind the vertex color buffer
             self.gl.bind_buffer(
                 WebGlRenderingContext::ARRAY_BUFFER,
-                Some(&self.color_buf.buf_ref),
+                Some(&color_buf.buf_ref),
             );
             self.gl.vertex_attrib_pointer_with_i32(
                 self.vertex_color,
@@ -285,13 +358,10 @@ impl Draw {
             );
             self.gl.enable_vertex_attrib_array(self.vertex_color);
         }
-        self.gl.use_program(Some(&self.program));
-        self.set_projection();
-
         self.gl.draw_arrays(
             WebGlRenderingContext::TRIANGLES,
             0,
-            (self.block_buf.data_len / 2) as i32,
+            (vertex_buf.data_len / 2) as i32,
         );
     }
 }
@@ -0,0 +1,737 @@
+//
+// Encoding and decoding of fumen (v115) data, the format used by
+// harddrop.com/fumen and the tetris-fumen library to share boards.
+//
+// A fumen is a list of pages, each with a 10 wide field of 23 rows (plus
+// a garbage row below) and optionally a piece. Fields are stored as run
+// length encoded differences to the field of the previous page.
+//
+use rstris::block::Block;
+use rstris::figure::Figure;
+use rstris::movement::Movement;
+use rstris::playfield::Playfield;
+use rstris::position::Position;
+
+const FUMEN_PREFIX: &str = "v115@";
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const FIELD_WIDTH: usize = 10;
+const FIELD_TOP: usize = 23;
+const FIELD_HEIGHT: usize = FIELD_TOP + 1;
+const FIELD_BLOCKS: usize = FIELD_HEIGHT * FIELD_WIDTH;
+
+// Fumen piece types
+const PIECE_EMPTY: u8 = 0;
+const PIECE_I: u8 = 1;
+const PIECE_L: u8 = 2;
+const PIECE_O: u8 = 3;
+const PIECE_Z: u8 = 4;
+const PIECE_T: u8 = 5;
+const PIECE_J: u8 = 6;
+const PIECE_S: u8 = 7;
+const PIECE_GRAY: u8 = 8;
+
+// Fumen rotations, numbered as they are encoded
+const ROTATION_REVERSE: u8 = 0;
+const ROTATION_RIGHT: u8 = 1;
+const ROTATION_SPAWN: u8 = 2;
+const ROTATION_LEFT: u8 = 3;
+
+// Fumen piece type for each of our block ids (index 8 is garbage)
+const BLOCK_TO_PIECE: [u8; 9] = [
+    PIECE_EMPTY,
+    PIECE_T,
+    PIECE_J,
+    PIECE_L,
+    PIECE_O,
+    PIECE_S,
+    PIECE_Z,
+    PIECE_I,
+    PIECE_GRAY,
+];
+
+fn block_to_piece(id: u8) -> u8 {
+    BLOCK_TO_PIECE
+        .get(id as usize)
+        .cloned()
+        .unwrap_or(PIECE_GRAY)
+}
+
+fn piece_to_block(piece: u8) -> u8 {
+    BLOCK_TO_PIECE
+        .iter()
+        .position(|p| *p == piece)
+        .unwrap_or(PIECE_GRAY as usize) as u8
+}
+
+// Blocks of a piece relative to its (SRS) rotation center, y pointing up
+fn piece_blocks(piece: u8, rotation: u8) -> [(i32, i32); 4] {
+    let blocks = match piece {
+        PIECE_I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        PIECE_L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        PIECE_O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        PIECE_Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        PIECE_T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        PIECE_J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        _ => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+    };
+    let mut rotated = blocks;
+    for (i, (x, y)) in blocks.iter().enumerate() {
+        rotated[i] = match rotation {
+            ROTATION_RIGHT => (*y, -x),
+            ROTATION_REVERSE => (-x, -y),
+            ROTATION_LEFT => (-y, *x),
+            _ => (*x, *y),
+        };
+    }
+    rotated
+}
+
+//
+// Offset from the position stored in a fumen to the rotation center of
+// a piece. Fumen keeps the same reference point for rotations that cover
+// the same blocks, which differs from the rotation center.
+//
+fn center_offset(piece: u8, rotation: u8) -> (i32, i32) {
+    match (piece, rotation) {
+        (PIECE_O, ROTATION_LEFT) => (1, -1),
+        (PIECE_O, ROTATION_REVERSE) => (1, 0),
+        (PIECE_O, ROTATION_SPAWN) => (0, -1),
+        (PIECE_I, ROTATION_REVERSE) => (1, 0),
+        (PIECE_I, ROTATION_LEFT) => (0, -1),
+        (PIECE_S, ROTATION_SPAWN) => (0, -1),
+        (PIECE_S, ROTATION_RIGHT) => (-1, 0),
+        (PIECE_Z, ROTATION_SPAWN) => (0, -1),
+        (PIECE_Z, ROTATION_LEFT) => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+// A piece on a fumen page. Position is the rotation center with y
+// counted upwards from the bottom row of the field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Piece {
+    piece: u8,
+    rotation: u8,
+    x: i32,
+    y: i32,
+}
+
+impl Piece {
+    fn blocks(&self) -> Vec<(i32, i32)> {
+        piece_blocks(self.piece, self.rotation)
+            .iter()
+            .map(|(x, y)| (self.x + x, self.y + y))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ActionFlags {
+    rise: bool,
+    mirror: bool,
+    colorize: bool,
+    comment: bool,
+    lock: bool,
+}
+
+// Field blocks in the order they are encoded, from the top row and
+// down, with the garbage row last
+#[derive(Clone)]
+struct Field {
+    blocks: [u8; FIELD_BLOCKS],
+}
+
+impl Field {
+    fn new() -> Self {
+        Field {
+            blocks: [PIECE_EMPTY; FIELD_BLOCKS],
+        }
+    }
+
+    // Index of block at x and y, where y = -1 is the garbage row
+    fn index(x: i32, y: i32) -> Option<usize> {
+        if x < 0 || x >= FIELD_WIDTH as i32 || y < -1 || y >= FIELD_TOP as i32 {
+            return None;
+        }
+        Some((FIELD_TOP as i32 - 1 - y) as usize * FIELD_WIDTH + x as usize)
+    }
+
+    fn get(&self, x: i32, y: i32) -> u8 {
+        Self::index(x, y).map_or(PIECE_EMPTY, |i| self.blocks[i])
+    }
+
+    fn set(&mut self, x: i32, y: i32, piece: u8) {
+        if let Some(i) = Self::index(x, y) {
+            self.blocks[i] = piece;
+        }
+    }
+
+    fn place(&mut self, piece: &Piece) {
+        for (x, y) in piece.blocks() {
+            self.set(x, y, piece.piece);
+        }
+    }
+
+    fn clear_lines(&mut self) {
+        let mut y = 0;
+        while y < FIELD_TOP as i32 {
+            let full = (0..FIELD_WIDTH as i32).all(|x| self.get(x, y) != PIECE_EMPTY);
+            if full {
+                for above in y..FIELD_TOP as i32 {
+                    for x in 0..FIELD_WIDTH as i32 {
+                        let piece = self.get(x, above + 1);
+                        self.set(x, above, piece);
+                    }
+                }
+            } else {
+                y += 1;
+            }
+        }
+    }
+
+    // Push the garbage row up from the bottom
+    fn rise(&mut self) {
+        for y in (0..FIELD_TOP as i32).rev() {
+            for x in 0..FIELD_WIDTH as i32 {
+                let piece = self.get(x, y - 1);
+                self.set(x, y, piece);
+            }
+        }
+        for x in 0..FIELD_WIDTH as i32 {
+            self.set(x, -1, PIECE_EMPTY);
+        }
+    }
+
+    fn mirror(&mut self) {
+        for y in 0..FIELD_TOP as i32 {
+            for x in 0..(FIELD_WIDTH / 2) as i32 {
+                let mirror_x = FIELD_WIDTH as i32 - 1 - x;
+                let left = self.get(x, y);
+                let right = self.get(mirror_x, y);
+                self.set(x, y, right);
+                self.set(mirror_x, y, left);
+            }
+        }
+    }
+}
+
+//
+// Write values as little endian base 64 digits
+//
+struct Encoder {
+    data: Vec<u8>,
+}
+
+impl Encoder {
+    fn push(&mut self, mut value: usize, digits: usize) {
+        for _ in 0..digits {
+            self.data.push(ENCODE_TABLE[value % 64]);
+            value /= 64;
+        }
+    }
+
+    fn encode_field(&mut self, prev: &Field, current: &Field) {
+        let diffs = prev
+            .blocks
+            .iter()
+            .zip(current.blocks.iter())
+            .map(|(prev, current)| (*current as usize + 8) - *prev as usize);
+        let mut run: Option<(usize, usize)> = None;
+        for diff in diffs {
+            run = match run {
+                Some((run_diff, count)) if run_diff == diff => Some((diff, count + 1)),
+                Some((run_diff, count)) => {
+                    self.push(run_diff * FIELD_BLOCKS + count - 1, 2);
+                    Some((diff, 1))
+                }
+                None => Some((diff, 1)),
+            };
+        }
+        if let Some((run_diff, count)) = run {
+            self.push(run_diff * FIELD_BLOCKS + count - 1, 2);
+        }
+        if current.blocks == prev.blocks {
+            // Number of following pages with the same field
+            self.push(0, 1);
+        }
+    }
+
+    fn encode_action(&mut self, piece: Option<&Piece>, flags: ActionFlags) {
+        let (piece, rotation, position) = match piece {
+            Some(piece) => {
+                let (dx, dy) = center_offset(piece.piece, piece.rotation);
+                let x = piece.x - dx;
+                let y = piece.y - dy;
+                let position = (FIELD_TOP as i32 - y - 1) * FIELD_WIDTH as i32 + x;
+                (piece.piece, piece.rotation, position as usize)
+            }
+            None => (PIECE_EMPTY, ROTATION_REVERSE, 0),
+        };
+        let mut value = usize::from(!flags.lock);
+        value = value * 2 + usize::from(flags.comment);
+        value = value * 2 + usize::from(flags.colorize);
+        value = value * 2 + usize::from(flags.mirror);
+        value = value * 2 + usize::from(flags.rise);
+        value = value * FIELD_BLOCKS + position;
+        value = value * 4 + rotation as usize;
+        value = value * 8 + piece as usize;
+        self.push(value, 3);
+    }
+
+    // Full fumen string, split with '?' in the same way as other tools
+    fn finish(self) -> String {
+        let data = String::from_utf8(self.data).unwrap();
+        let mut fumen = String::from(FUMEN_PREFIX);
+        if data.len() < 41 {
+            fumen.push_str(&data);
+            return fumen;
+        }
+        let head = data.len().min(42);
+        fumen.push_str(&data[..head]);
+        let mut rest = &data[head..];
+        while !rest.is_empty() {
+            let split = rest.len().min(47);
+            fumen.push('?');
+            fumen.push_str(&rest[..split]);
+            rest = &rest[split..];
+        }
+        fumen
+    }
+}
+
+//
+// Read values written by Encoder
+//
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn poll(&mut self, digits: usize) -> Result<usize, String> {
+        if self.data.len() < digits {
+            return Err("Fumen data ended unexpectedly".to_string());
+        }
+        let mut value = 0;
+        for (i, c) in self.data[..digits].iter().enumerate() {
+            let digit = match ENCODE_TABLE.iter().position(|e| e == c) {
+                Some(digit) => digit,
+                None => return Err(format!("Invalid character in fumen: {}", *c as char)),
+            };
+            value += digit << (6 * i);
+        }
+        self.data = &self.data[digits..];
+        Ok(value)
+    }
+
+    // Apply field differences and return the number of following pages
+    // that repeat the field
+    fn decode_field(&mut self, field: &mut Field) -> Result<usize, String> {
+        let mut repeat = 0;
+        let mut index = 0;
+        while index < FIELD_BLOCKS {
+            let value = self.poll(2)?;
+            let diff = value / FIELD_BLOCKS;
+            let count = value % FIELD_BLOCKS + 1;
+            if diff == 8 && count == FIELD_BLOCKS {
+                repeat = self.poll(1)?;
+            }
+            if index + count > FIELD_BLOCKS || diff > 16 {
+                return Err("Invalid field in fumen".to_string());
+            }
+            for block in &mut field.blocks[index..index + count] {
+                let piece = *block as usize + diff;
+                if !(8..=16).contains(&piece) {
+                    return Err("Invalid field in fumen".to_string());
+                }
+                *block = (piece - 8) as u8;
+            }
+            index += count;
+        }
+        Ok(repeat)
+    }
+
+    fn decode_action(&mut self) -> Result<(Option<Piece>, ActionFlags), String> {
+        let mut value = self.poll(3)?;
+        let piece = (value % 8) as u8;
+        value /= 8;
+        let rotation = (value % 4) as u8;
+        value /= 4;
+        let position = (value % FIELD_BLOCKS) as i32;
+        value /= FIELD_BLOCKS;
+        let flags = ActionFlags {
+            rise: value & 1 == 1,
+            mirror: (value >> 1) & 1 == 1,
+            colorize: (value >> 2) & 1 == 1,
+            comment: (value >> 3) & 1 == 1,
+            lock: (value >> 4) & 1 == 0,
+        };
+
+        if piece == PIECE_EMPTY || piece == PIECE_GRAY {
+            return Ok((None, flags));
+        }
+        let (dx, dy) = center_offset(piece, rotation);
+        let x = position % FIELD_WIDTH as i32;
+        let y = FIELD_TOP as i32 - position / FIELD_WIDTH as i32 - 1;
+        let piece = Piece {
+            piece,
+            rotation,
+            x: x + dx,
+            y: y + dy,
+        };
+        Ok((Some(piece), flags))
+    }
+
+    // Comments are not used, just step past them
+    fn skip_comment(&mut self) -> Result<(), String> {
+        let length = self.poll(2)?;
+        for _ in 0..length.div_ceil(4) {
+            self.poll(5)?;
+        }
+        Ok(())
+    }
+}
+
+//
+// Conversion between our figures and fumen pieces
+//
+
+fn figure_id(fig: &Figure) -> u8 {
+    fig.face(0).iter().map(|(_, _, id)| *id).next().unwrap_or(0)
+}
+
+// Blocks sorted and moved to start at 0,0
+fn normalize_blocks(blocks: &mut [(i32, i32)]) {
+    let min_x = blocks.iter().map(|b| b.0).min().unwrap_or(0);
+    let min_y = blocks.iter().map(|b| b.1).min().unwrap_or(0);
+    for block in blocks.iter_mut() {
+        *block = (block.0 - min_x, block.1 - min_y);
+    }
+    blocks.sort();
+}
+
+fn min_block(blocks: &[(i32, i32)]) -> (i32, i32) {
+    let min_x = blocks.iter().map(|b| b.0).min().unwrap_or(0);
+    let min_y = blocks.iter().map(|b| b.1).min().unwrap_or(0);
+    (min_x, min_y)
+}
+
+fn figure_to_piece(fig: &Figure, pos: Position, pf_height: u32) -> Result<Piece, String> {
+    let piece = block_to_piece(figure_id(fig));
+    let mut blocks: Vec<(i32, i32)> = fig
+        .face(pos.dir())
+        .iter()
+        .map(|(x, y, _)| {
+            let x = i32::from(*x) + pos.x();
+            let y = pf_height as i32 - 1 - (i32::from(*y) + pos.y());
+            (x, y)
+        })
+        .collect();
+    let min = min_block(&blocks);
+    normalize_blocks(&mut blocks);
+    for rotation in &[
+        ROTATION_SPAWN,
+        ROTATION_RIGHT,
+        ROTATION_REVERSE,
+        ROTATION_LEFT,
+    ] {
+        let mut piece_blocks = piece_blocks(piece, *rotation).to_vec();
+        let piece_min = min_block(&piece_blocks);
+        normalize_blocks(&mut piece_blocks);
+        if piece_blocks == blocks {
+            return Ok(Piece {
+                piece,
+                rotation: *rotation,
+                x: min.0 - piece_min.0,
+                y: min.1 - piece_min.1,
+            });
+        }
+    }
+    Err("Figure has no matching fumen piece".to_string())
+}
+
+fn piece_to_figure(
+    piece: &Piece,
+    figures: &[Figure],
+    pf_height: u32,
+) -> Result<(Figure, Position), String> {
+    let id = piece_to_block(piece.piece);
+    let fig = match figures.iter().find(|fig| figure_id(fig) == id) {
+        Some(fig) => fig,
+        None => return Err(format!("No figure for fumen piece {}", piece.piece)),
+    };
+    let mut blocks: Vec<(i32, i32)> = piece
+        .blocks()
+        .iter()
+        .map(|(x, y)| (*x, pf_height as i32 - 1 - y))
+        .collect();
+    let min = min_block(&blocks);
+    normalize_blocks(&mut blocks);
+
+    // Try each rotation of the figure until one covers the same blocks
+    let mut pos = Position::new((0, 0, 0));
+    for _ in 0..4 {
+        let mut face_blocks: Vec<(i32, i32)> = fig
+            .face(pos.dir())
+            .iter()
+            .map(|(x, y, _)| (i32::from(*x), i32::from(*y)))
+            .collect();
+        let face_min = min_block(&face_blocks);
+        normalize_blocks(&mut face_blocks);
+        if face_blocks == blocks {
+            let x = min.0 - face_min.0;
+            let y = min.1 - face_min.1;
+            return Ok((fig.clone(), Position::new((x, y, pos.dir()))));
+        }
+        pos = Position::apply_move(&pos, Movement::RotateCW);
+    }
+    Err(format!(
+        "No rotation of figure matches fumen piece {}",
+        piece.piece
+    ))
+}
+
+fn playfield_to_field(pf: &Playfield) -> Result<Field, String> {
+    if pf.width() as usize != FIELD_WIDTH {
+        return Err(format!(
+            "Fumen needs a playfield {} blocks wide",
+            FIELD_WIDTH
+        ));
+    }
+    let mut field = Field::new();
+    for pf_y in 0..pf.height() as i32 {
+        let y = pf.height() as i32 - 1 - pf_y;
+        for x in 0..FIELD_WIDTH as i32 {
+            if let Block::Set(id) = pf.get_block((x, pf_y).into()) {
+                if y >= FIELD_TOP as i32 {
+                    return Err("Playfield is too high to fit in a fumen".to_string());
+                }
+                field.set(x, y, block_to_piece(id));
+            }
+        }
+    }
+    Ok(field)
+}
+
+fn field_to_playfield(field: &Field, height: u32) -> Result<Playfield, String> {
+    let mut pf = Playfield::new("Fumen", FIELD_WIDTH as u32, height);
+    for y in 0..FIELD_TOP as i32 {
+        for x in 0..FIELD_WIDTH as i32 {
+            let piece = field.get(x, y);
+            if piece == PIECE_EMPTY {
+                continue;
+            }
+            if y >= height as i32 {
+                return Err("Fumen field is too high for the playfield".to_string());
+            }
+            let pf_y = height as i32 - 1 - y;
+            pf.set_block((x, pf_y).into(), Block::Set(piece_to_block(piece)));
+        }
+    }
+    Ok(pf)
+}
+
+// A decoded fumen page
+pub struct FumenPage {
+    pub pf: Playfield,
+    pub figure: Option<(Figure, Position)>,
+}
+
+//
+// Encode a playfield (which must be 10 blocks wide) and the figure
+// currently being played as a single page fumen.
+//
+pub fn encode_fumen(pf: &Playfield, figure: Option<&(Figure, Position)>) -> Result<String, String> {
+    let field = playfield_to_field(pf)?;
+    let piece = match figure {
+        Some((fig, pos)) => Some(figure_to_piece(fig, *pos, pf.height())?),
+        None => None,
+    };
+    let flags = ActionFlags {
+        colorize: true,
+        lock: true,
+        ..Default::default()
+    };
+    let mut encoder = Encoder { data: Vec::new() };
+    encoder.encode_field(&Field::new(), &field);
+    encoder.encode_action(piece.as_ref(), flags);
+    Ok(encoder.finish())
+}
+
+//
+// Decode all pages of a fumen into playfields of the given height and
+// figures from the list of available figures.
+//
+pub fn decode_fumen(
+    fumen: &str,
+    figures: &[Figure],
+    height: u32,
+) -> Result<Vec<FumenPage>, String> {
+    // Accept full URLs as well as just the data
+    let data = match fumen.find(FUMEN_PREFIX) {
+        Some(start) => &fumen[start + FUMEN_PREFIX.len()..],
+        None => return Err("Not a v115 fumen".to_string()),
+    };
+    let data: Vec<u8> = data.bytes().filter(|c| *c != b'?').collect();
+    let mut decoder = Decoder { data: &data };
+
+    let mut pages = Vec::new();
+    let mut field = Field::new();
+    let mut repeat = 0;
+    while !decoder.is_empty() {
+        if repeat == 0 {
+            repeat = decoder.decode_field(&mut field)?;
+        } else {
+            repeat -= 1;
+        }
+        let (piece, flags) = decoder.decode_action()?;
+        if flags.comment {
+            decoder.skip_comment()?;
+        }
+        let figure = match piece {
+            Some(ref piece) => Some(piece_to_figure(piece, figures, height)?),
+            None => None,
+        };
+        pages.push(FumenPage {
+            pf: field_to_playfield(&field, height)?,
+            figure,
+        });
+
+        // Field of next page starts out from this one
+        if flags.lock {
+            if let Some(ref piece) = piece {
+                field.place(piece);
+            }
+            field.clear_lines();
+            if flags.rise {
+                field.rise();
+            }
+            if flags.mirror {
+                field.mirror();
+            }
+        }
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_playfield::*;
+
+    fn empty_pf() -> Playfield {
+        Playfield::new("Playfield", 10, 23)
+    }
+
+    #[test]
+    fn empty_field() {
+        let fumen = encode_fumen(&empty_pf(), None).unwrap();
+        assert_eq!(fumen, "v115@vhAAgH");
+
+        let pages = decode_fumen("v115@vhAAgH", &crate::init_figures(), 23).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(
+            playfield_to_ascii(&pages[0].pf),
+            playfield_to_ascii(&empty_pf())
+        );
+        assert!(pages[0].figure.is_none());
+    }
+
+    #[test]
+    fn garbage_field() {
+        let mut pf = empty_pf();
+        for x in 0..9 {
+            pf.set_block((x, 22).into(), Block::Set(8));
+        }
+        let fumen = encode_fumen(&pf, None).unwrap();
+        assert_eq!(fumen, "v115@bhI8KeAgH");
+
+        // Decode to a higher playfield
+        let pages = decode_fumen(&fumen, &crate::init_figures(), 25).unwrap();
+        let ascii = playfield_to_ascii(&pages[0].pf);
+        assert!(ascii.ends_with("..........\n888888888.\n"));
+    }
+
+    #[test]
+    fn figure_round_trip() {
+        let figures = crate::init_figures();
+        for fig in &figures {
+            let mut pos = Position::new((4, 10, 0));
+            for _ in 0..4 {
+                let fumen = encode_fumen(&empty_pf(), Some(&(fig.clone(), pos))).unwrap();
+                let pages = decode_fumen(&fumen, &figures, 23).unwrap();
+                let (decoded_fig, decoded_pos) = pages[0].figure.clone().unwrap();
+                assert_eq!(figure_id(&decoded_fig), figure_id(fig));
+
+                // Rotations may differ for symmetric figures, but the
+                // blocks covered must be the same
+                let mut pf = empty_pf();
+                let mut decoded_pf = empty_pf();
+                fig.place(&mut pf, pos);
+                decoded_fig.place(&mut decoded_pf, decoded_pos);
+                assert_eq!(playfield_to_ascii(&pf), playfield_to_ascii(&decoded_pf));
+
+                pos = Position::apply_move(&pos, Movement::RotateCW);
+            }
+        }
+    }
+
+    #[test]
+    fn t_piece() {
+        // T in spawn rotation (pointing up) with its center at x 4 on the
+        // bottom row
+        let pages = decode_fumen("v115@vhAVQJ", &crate::init_figures(), 23).unwrap();
+        let (fig, pos) = pages[0].figure.clone().unwrap();
+        let mut pf = empty_pf();
+        fig.place(&mut pf, pos);
+        let ascii = playfield_to_ascii(&pf);
+        assert!(ascii.ends_with("..........\n....1.....\n...111....\n"));
+
+        let fumen = encode_fumen(&empty_pf(), Some(&(fig, pos))).unwrap();
+        assert_eq!(fumen, "v115@vhAVQJ");
+    }
+
+    #[test]
+    fn multiple_pages() {
+        // Two pages where the T piece of the first page is locked
+        let mut encoder = Encoder { data: Vec::new() };
+        let piece = Piece {
+            piece: PIECE_T,
+            rotation: ROTATION_SPAWN,
+            x: 4,
+            y: 0,
+        };
+        let flags = ActionFlags {
+            colorize: true,
+            lock: true,
+            ..Default::default()
+        };
+        let mut locked = Field::new();
+        locked.place(&piece);
+        encoder.encode_field(&Field::new(), &Field::new());
+        encoder.encode_action(Some(&piece), flags);
+        encoder.encode_field(&locked, &locked);
+        encoder.encode_action(None, ActionFlags::default());
+        let fumen = encoder.finish();
+
+        let pages = decode_fumen(&fumen, &crate::init_figures(), 23).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].figure.is_some());
+        assert!(pages[1].figure.is_none());
+        let ascii = playfield_to_ascii(&pages[1].pf);
+        assert!(ascii.ends_with("..........\n....1.....\n...111....\n"));
+    }
+
+    #[test]
+    fn invalid_fumen() {
+        let figures = crate::init_figures();
+        assert!(decode_fumen("v114@vhAAgH", &figures, 23).is_err());
+        assert!(decode_fumen("v115@vhA", &figures, 23).is_err());
+        assert!(decode_fumen("v115@vh!AgH", &figures, 23).is_err());
+        assert!(encode_fumen(&Playfield::new("Playfield", 8, 20), None).is_err());
+    }
+}
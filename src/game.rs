@@ -1,13 +1,19 @@
+use crate::ascii_playfield::*;
+use crate::rotation_system::*;
 use crate::utils::*;
 
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, VecDeque};
 
+use rstris::block::Block;
 use rstris::figure::*;
 use rstris::movement::*;
 use rstris::playfield::*;
 use rstris::position::Position;
 
+use rand::rngs::SmallRng;
+use rand::{RngExt, SeedableRng};
+
 #[derive(Debug, Clone)]
 struct MoveAndTime {
     movement: Movement,
@@ -33,166 +39,1389 @@ impl PartialEq for MoveAndTime {
 struct MoveQueue {
     // Queues of moves to be executed
     queue: BinaryHeap<MoveAndTime>,
-
-    // Keep track of when last move was dequeued
-    last_move_time: [u64; 6],
 }
 
 impl MoveQueue {
     fn new() -> Self {
         MoveQueue {
             queue: BinaryHeap::new(),
-            last_move_time: [0; 6],
-        }
-    }
-
-    fn movement_to_index(movement: Movement) -> usize {
-        match movement {
-            Movement::MoveLeft => 0,
-            Movement::MoveRight => 1,
-            Movement::MoveDown => 2,
-            Movement::MoveUp => 3,
-            Movement::RotateCW => 4,
-            Movement::RotateCCW => 5,
         }
     }
 
-    fn add_move(&mut self, movement: Movement, ticks: u64) {
+    fn add_move(&mut self, movement: Movement, frame: u64) {
         let move_time = MoveAndTime {
             movement,
-            time: ticks,
+            time: frame,
         };
         self.queue.push(move_time);
     }
 
-    pub fn pop_next_move(&mut self, ticks: u64) -> Option<MoveAndTime> {
+    pub fn pop_next_move(&mut self, frame: u64) -> Option<MoveAndTime> {
         if let Some(move_and_time) = self.queue.peek() {
-            if move_and_time.time <= ticks {
-                self.last_move_time[Self::movement_to_index(move_and_time.movement)] =
-                    move_and_time.time;
+            if move_and_time.time <= frame {
                 return self.queue.pop();
             }
         }
         None
     }
 
-    pub fn time_last_move(&self, movement: Movement) -> u64 {
-        self.last_move_time[Self::movement_to_index(movement)]
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+// Block id used for garbage rows
+pub const GARBAGE_BLOCK_ID: u8 = 8;
+
+// The phases a game cycles through for every figure
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    // Figure is falling with gravity
+    Falling,
+    // Figure has landed and is waiting for the lock delay to pass
+    Locking,
+    // Full lines are waiting for the line clear delay to pass
+    LineClear,
+    // Waiting for the entry delay before the next figure is placed
+    Spawn,
+    // Rows are being cleared to make room after topping out
+    BoardClear,
+}
+
+// What happens when there is no room for a new figure
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TopOut {
+    GameOver,
+    // Clear this many rows from the top and keep playing
+    ClearRows(u32),
+    // Clear the whole playfield and keep playing
+    ClearBoard,
+}
+
+// Garbage rows rising from the bottom at a shrinking interval
+#[derive(Debug, Clone)]
+pub struct RisingFloor {
+    // Frames until the first row rises and between the first rows
+    pub start_interval: u64,
+    // The interval never gets shorter than this
+    pub min_interval: u64,
+    // How much shorter the interval gets for each row risen
+    pub interval_step: u64,
+}
+
+// Levels going up with the lines cleared, each with its own gravity
+#[derive(Debug, Clone)]
+pub struct Levels {
+    pub start_level: u32,
+    // Frames for a figure to fall one row at each level. Levels beyond
+    // the table use its last entry.
+    pub frames_per_row: Vec<u32>,
+    // Lines to clear for the first level up and for each one after that
+    pub first_level_lines: u32,
+    pub level_lines: u32,
+}
+
+impl Levels {
+    fn level(&self, lines_cleared: u32) -> u32 {
+        if lines_cleared < self.first_level_lines {
+            self.start_level
+        } else {
+            self.start_level
+                + 1
+                + (lines_cleared - self.first_level_lines) / self.level_lines.max(1)
+        }
+    }
+
+    fn gravity(&self, level: u32) -> f32 {
+        let frames = self
+            .frames_per_row
+            .get(level as usize)
+            .or_else(|| self.frames_per_row.last())
+            .copied()
+            .unwrap_or(1);
+        1.0 / frames.max(1) as f32
     }
+}
+
+// Points for clearing 1-4 lines at once, times the level plus one
+const LINE_CLEAR_POINTS: [u64; 5] = [0, 40, 100, 300, 1200];
+
+// Configuration of a game. All times are in frames
+#[derive(Debug, Clone)]
+pub struct GameConfig {
+    // Number of cells (fractions allowed) a figure falls per frame.
+    // Anything at or above the playfield height is instant (20G).
+    // Replaced by the gravity of the level when playing with levels.
+    pub gravity: f32,
+    // Time a landed figure can still be moved before it is locked
+    pub lock_delay: u64,
+    // Time full lines stay on the playfield before being removed
+    pub line_clear_delay: u64,
+    // Time from a figure being locked (or lines cleared) until the
+    // next figure is placed (ARE)
+    pub entry_delay: u64,
+    // Push up garbage rows from the bottom on a timer
+    pub rising_floor: Option<RisingFloor>,
+    // Number of figures that can be undone (0 to disable undo)
+    pub undo_depth: usize,
+    // How figures spawn, rotate and kick
+    pub rotation: Rotation,
+    // Allow putting a figure on hold
+    pub hold: bool,
+    // Speed up as lines are cleared
+    pub levels: Option<Levels>,
+    // What to do when there is no room for a new figure
+    pub top_out: TopOut,
+    // Every block of a figure takes 2x2 cells and figures move sideways
+    // two cells at a time
+    pub big: bool,
+    // Number of upcoming figures known ahead (at least one)
+    pub preview: usize,
+}
 
-    pub fn time_since_move(&self, ticks: u64, movement: Movement) -> i64 {
-        ticks as i64 - self.time_last_move(movement) as i64
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            gravity: 1.0 / 60.0,
+            lock_delay: 30,
+            line_clear_delay: 40,
+            entry_delay: 25,
+            rising_floor: None,
+            undo_depth: 0,
+            rotation: Rotation::Basic,
+            hold: true,
+            levels: None,
+            top_out: TopOut::GameOver,
+            big: false,
+            preview: 1,
+        }
     }
+}
 
-    pub fn clear(&mut self) {
-        self.queue.clear();
+impl GameConfig {
+    //
+    // Rules of NES Tetris starting at the given level: its gravity table
+    // and level ups, NES rotation, no hold and figures locking as soon as
+    // they land.
+    //
+    pub fn nes_classic(start_level: u32) -> Self {
+        let first_level_lines =
+            (start_level * 10 + 10).min((start_level * 10).saturating_sub(50).max(100));
+        GameConfig {
+            gravity: 0.0,
+            lock_delay: 0,
+            line_clear_delay: 18,
+            entry_delay: 10,
+            rising_floor: None,
+            undo_depth: 0,
+            rotation: Rotation::Nes,
+            hold: false,
+            levels: Some(Levels {
+                start_level,
+                frames_per_row: vec![
+                    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2,
+                    2, 2, 2, 2, 2, 1,
+                ],
+                first_level_lines,
+                level_lines: 10,
+            }),
+            top_out: TopOut::GameOver,
+            big: false,
+            preview: 1,
+        }
     }
 }
 
+// Where the figures to play come from
+#[derive(Clone)]
+enum FigureSource {
+    // Randomly pick any of the available figures
+    Random(Vec<Figure>),
+    // Play the figures in order until there are none left
+    Sequence(VecDeque<Figure>),
+}
+
+impl FigureSource {
+    fn next_figure(&mut self, rng: &mut SmallRng) -> Option<Figure> {
+        match self {
+            FigureSource::Random(figures) => {
+                let next_figure = rng.random_range(0..figures.len());
+                Some(figures[next_figure].clone())
+            }
+            FigureSource::Sequence(figures) => figures.pop_front(),
+        }
+    }
+
+    fn scaled(self, scale: usize) -> Self {
+        match self {
+            FigureSource::Random(figures) => {
+                FigureSource::Random(figures.iter().map(|fig| scale_figure(fig, scale)).collect())
+            }
+            FigureSource::Sequence(figures) => {
+                FigureSource::Sequence(figures.iter().map(|fig| scale_figure(fig, scale)).collect())
+            }
+        }
+    }
+}
+
+// Size of big figure blocks, see GameConfig::big
+const BIG_SCALE: usize = 2;
+
+//
+// Figure with every block grown to scale x scale blocks. The face is put
+// in a square box so that it still rotates in place.
+//
+fn scale_figure(fig: &Figure, scale: usize) -> Figure {
+    let face = fig.face(0);
+    let size = face.iter().map(|(x, y, _)| x.max(y) + 1).max().unwrap_or(0) as usize;
+    let mut rows = vec![vec![Block::Clear; size * scale]; size * scale];
+    for (x, y, id) in face {
+        for dy in 0..scale {
+            for dx in 0..scale {
+                rows[*y as usize * scale + dy][*x as usize * scale + dx] = Block::Set(*id);
+            }
+        }
+    }
+    let rows: Vec<&[Block]> = rows.iter().map(|row| row.as_slice()).collect();
+    Figure::new_from_face("Big", &rows)
+}
+
+// State of a game as a figure spawns, to be restored by an undo
+struct Snapshot {
+    pf: Playfield,
+    lock_frames: Vec<Option<u64>>,
+    figure_source: FigureSource,
+    rng: SmallRng,
+    preview: VecDeque<Figure>,
+    current_figure: (Figure, Position),
+    held_figure: Option<Figure>,
+    hold_used: bool,
+    lines_cleared: u32,
+    figures_placed: u32,
+    perfect_clears: u32,
+    score: u64,
+    rise_interval: u64,
+    // Frames left until the next row rises
+    rise_time_left: u64,
+}
+
 pub struct Game {
     pf: Playfield,
-    down_step_time: u64,
+    config: GameConfig,
+    rotation_system: Box<dyn RotationSystem>,
 
-    // All available figures
-    available_figures: Vec<Figure>,
+    // Source of figures to play and the random generator used for
+    // picking figures and garbage holes
+    figure_source: FigureSource,
+    rng: SmallRng,
 
-    // Next figure to be played
-    next_figure: Figure,
+    // Figures to be played next, in order
+    preview: VecDeque<Figure>,
 
     // Current figure being played
     current_figure: Option<(Figure, Position)>,
 
+    // Figure put on hold, if it has been used for the current figure and
+    // if a hold is waiting for the next figure to spawn
+    held_figure: Option<Figure>,
+    hold_used: bool,
+    hold_buffered: bool,
+
+    // Figure that had no room to spawn, to be played once the board has
+    // been cleared
+    topped_out_figure: Option<Figure>,
+
+    // Current phase and the frame it was entered
+    phase: Phase,
+    phase_start: u64,
+
+    // Lines waiting to be removed during the line clear phase
+    clearing_lines: Vec<u32>,
+
+    // Frame each block of the playfield was locked, row by row. Blocks
+    // not placed by a figure (e.g. garbage) have no lock frame.
+    lock_frames: Vec<Option<u64>>,
+
+    // Frame of the last update
+    frame: u64,
+
+    // Current level, its gravity, the fraction of a cell the current
+    // figure has fallen and the frame gravity was last applied
+    level: u32,
+    gravity: f32,
+    gravity_acc: f32,
+    gravity_time: u64,
+
+    // Interval and frame of the next row for a rising floor
+    rise_interval: u64,
+    next_rise: u64,
+
+    // Game statistics
+    lines_cleared: u32,
+    figures_placed: u32,
+    perfect_clears: u32,
+    score: u64,
+
     game_over: bool,
 
     // Queues of moves to be executed
     move_queue: MoveQueue,
+
+    // Snapshots taken at the latest spawns, the last one being of the
+    // current figure
+    snapshots: VecDeque<Snapshot>,
 }
 
 impl Game {
-    pub fn new(pf: Playfield, available_figures: Vec<Figure>, down_step_time: u64) -> Self {
-        Game {
+    pub fn new(pf: Playfield, available_figures: Vec<Figure>, config: GameConfig) -> Self {
+        Self::new_seeded(pf, available_figures, config, rand::random())
+    }
+
+    // Create a game where the random figures always come in the same
+    // order for the same seed
+    pub fn new_seeded(
+        pf: Playfield,
+        available_figures: Vec<Figure>,
+        config: GameConfig,
+        seed: u64,
+    ) -> Self {
+        Self::with_figure_source(pf, FigureSource::Random(available_figures), config, seed)
+    }
+
+    // Create a game where the figures are played in the given order
+    pub fn new_with_sequence(pf: Playfield, sequence: Vec<Figure>, config: GameConfig) -> Self {
+        Self::with_figure_source(
+            pf,
+            FigureSource::Sequence(sequence.into()),
+            config,
+            rand::random(),
+        )
+    }
+
+    fn with_figure_source(
+        pf: Playfield,
+        mut figure_source: FigureSource,
+        config: GameConfig,
+        seed: u64,
+    ) -> Self {
+        if config.big {
+            figure_source = figure_source.scaled(BIG_SCALE);
+        }
+        let rise_interval = config
+            .rising_floor
+            .as_ref()
+            .map_or(0, |rising_floor| rising_floor.start_interval);
+        let lock_frames = vec![None; (pf.width() * pf.height()) as usize];
+        let gravity = config.gravity;
+        let rng = SmallRng::seed_from_u64(seed);
+        let mut game = Game {
+            rotation_system: config.rotation.system(),
+            rise_interval,
+            next_rise: rise_interval,
             pf,
-            down_step_time,
-            next_figure: Self::randomize_figure(&available_figures).clone(),
-            available_figures,
+            config,
+            preview: VecDeque::new(),
+            figure_source,
+            rng,
             current_figure: None,
+            held_figure: None,
+            hold_used: false,
+            hold_buffered: false,
+            topped_out_figure: None,
+            phase: Phase::Spawn,
+            phase_start: 0,
+            clearing_lines: Vec::new(),
+            lock_frames,
+            frame: 0,
+            level: 0,
+            gravity,
+            gravity_acc: 0.0,
+            gravity_time: 0,
+            lines_cleared: 0,
+            figures_placed: 0,
+            perfect_clears: 0,
+            score: 0,
             game_over: false,
             move_queue: MoveQueue::new(),
+            snapshots: VecDeque::new(),
+        };
+        game.fill_preview();
+        game.update_level();
+        game
+    }
+
+    pub fn playfield(&self) -> &Playfield {
+        &self.pf
+    }
+
+    // Playfield in text form, see ascii_playfield
+    pub fn dump_playfield(&self) -> String {
+        playfield_to_ascii(&self.pf)
+    }
+
+    // Replace the playfield with one in text form. It must be of the
+    // same size as the current one.
+    pub fn load_playfield(&mut self, text: &str) -> Result<(), String> {
+        let pf = playfield_from_ascii(text)?;
+        if pf.width() != self.pf.width() || pf.height() != self.pf.height() {
+            return Err(format!(
+                "Playfield is {}x{}, expected {}x{}",
+                pf.width(),
+                pf.height(),
+                self.pf.width(),
+                self.pf.height()
+            ));
         }
+        self.pf = pf;
+        for lock_frame in self.lock_frames.iter_mut() {
+            *lock_frame = None;
+        }
+        Ok(())
     }
 
-    fn randomize_figure(figures: &[Figure]) -> &Figure {
-        let next_figure = (rand::random::<u8>() % figures.len() as u8) as usize;
-        &figures[next_figure]
+    // Frame the block at the given position was locked by a figure
+    pub fn block_lock_frame(&self, x: u32, y: u32) -> Option<u64> {
+        if x >= self.pf.width() || y >= self.pf.height() {
+            return None;
+        }
+        self.lock_frames[(y * self.pf.width() + x) as usize]
     }
 
-    pub fn playfield(&self) -> &Playfield {
-        &self.pf
+    fn set_block_lock_frame(&mut self, x: i32, y: i32, lock_frame: Option<u64>) {
+        let (width, height) = (self.pf.width() as i32, self.pf.height() as i32);
+        if x >= 0 && x < width && y >= 0 && y < height {
+            self.lock_frames[(y * width + x) as usize] = lock_frame;
+        }
+    }
+
+    //
+    // Push a row of garbage up from the bottom of the playfield, with a
+    // hole at the given column. The game is over if blocks are pushed out
+    // over the top.
+    //
+    pub fn push_garbage_row(&mut self, hole: u32) {
+        let width = self.pf.width() as i32;
+        let height = self.pf.height() as i32;
+        if (0..width).any(|x| self.pf.get_block((x, 0).into()).is_set()) {
+            console_log!("Game over (topped out)");
+            self.game_over = true;
+        }
+        for y in 0..height - 1 {
+            for x in 0..width {
+                let block = self.pf.get_block((x, y + 1).into());
+                self.pf.set_block((x, y).into(), block);
+            }
+        }
+        for x in 0..width {
+            let block = if x == hole as i32 {
+                Block::Clear
+            } else {
+                Block::Set(GARBAGE_BLOCK_ID)
+            };
+            self.pf.set_block((x, height - 1).into(), block);
+        }
+        self.lock_frames.drain(..width as usize);
+        self.lock_frames
+            .extend(std::iter::repeat_n(None, width as usize));
+
+        // Lines waiting to be cleared have moved up as well
+        for line in self.clearing_lines.iter_mut() {
+            *line = line.saturating_sub(1);
+        }
+
+        // Push the current figure up along with the blocks it is about to
+        // land on
+        if let Some((ref fig, ref mut pos)) = self.current_figure {
+            if fig.test_collision(&self.pf, *pos) {
+                let up_pos = Position::apply_move(pos, Movement::MoveUp);
+                if fig.test_collision(&self.pf, up_pos) {
+                    console_log!("Game over (figure pushed into blocks)");
+                    self.game_over = true;
+                } else {
+                    *pos = up_pos;
+                }
+            }
+        }
+    }
+
+    // Push a row of garbage with the hole at a column drawn from the
+    // game's own random numbers
+    pub fn push_random_garbage_row(&mut self) {
+        let hole = self.rng.random_range(0..self.pf.width());
+        self.push_garbage_row(hole);
+    }
+
+    // Number of garbage rows rising per frame
+    pub fn garbage_rise_rate(&self) -> f32 {
+        match self.config.rising_floor {
+            Some(_) => 1.0 / self.rise_interval.max(1) as f32,
+            None => 0.0,
+        }
+    }
+
+    fn update_rising_floor(&mut self, frame: u64) {
+        if let Some(ref rising_floor) = self.config.rising_floor {
+            if frame < self.next_rise {
+                return;
+            }
+            self.rise_interval = self
+                .rise_interval
+                .saturating_sub(rising_floor.interval_step)
+                .max(rising_floor.min_interval);
+            self.next_rise = frame + self.rise_interval;
+            self.push_random_garbage_row();
+        }
+    }
+
+    // Number of rows with garbage left in them
+    pub fn garbage_rows(&self) -> u32 {
+        self.pf
+            .blocks()
+            .row_iter()
+            .filter(|row| {
+                row.iter()
+                    .any(|b| matches!(b, Block::Set(GARBAGE_BLOCK_ID)))
+            })
+            .count() as u32
     }
 
     pub fn current_figure(&self) -> &Option<(Figure, Position)> {
         &self.current_figure
     }
 
-    pub fn down_step_time(&self) -> u64 {
-        self.down_step_time
+    // Figure to be played after the current one
+    pub fn next_figure(&self) -> Option<&Figure> {
+        self.preview.front()
+    }
+
+    // Figures to be played after the current one, in order
+    pub fn preview(&self) -> &VecDeque<Figure> {
+        &self.preview
+    }
+
+    pub fn lines_cleared(&self) -> u32 {
+        self.lines_cleared
+    }
+
+    pub fn figures_placed(&self) -> u32 {
+        self.figures_placed
+    }
+
+    // Number of times lines were cleared leaving an empty playfield
+    pub fn perfect_clears(&self) -> u32 {
+        self.perfect_clears
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.config.rotation
+    }
+
+    // Change how figures rotate from now on
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.config.rotation = rotation;
+        self.rotation_system = rotation.system();
+    }
+
+    pub fn gravity(&self) -> f32 {
+        self.gravity
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    // Set the level and its gravity from the lines cleared
+    fn update_level(&mut self) {
+        if let Some(ref levels) = self.config.levels {
+            self.level = levels.level(self.lines_cleared);
+            self.gravity = levels.gravity(self.level);
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    // Lines being removed while in the line clear phase
+    pub fn clearing_lines(&self) -> &[u32] {
+        &self.clearing_lines
+    }
+
+    // How far into the current phase the game is, from 0.0 to 1.0.
+    // Phases without a set duration are always reported as 0.0.
+    pub fn phase_progress(&self) -> f32 {
+        let duration = match self.phase {
+            Phase::Falling => 0,
+            Phase::Locking => self.config.lock_delay,
+            Phase::LineClear | Phase::BoardClear => self.config.line_clear_delay,
+            Phase::Spawn => self.config.entry_delay,
+        };
+        if duration == 0 {
+            return 0.0;
+        }
+        (self.phase_time(self.frame) as f32 / duration as f32).min(1.0)
+    }
+
+    pub fn add_move(&mut self, movement: Movement, frame: u64) {
+        self.move_queue.add_move(movement, frame);
+    }
+
+    // Drop all moves not yet made
+    pub fn clear_moves(&mut self) {
+        self.move_queue.clear();
+    }
+
+    fn enter_phase(&mut self, phase: Phase, frame: u64) {
+        self.phase = phase;
+        self.phase_start = frame;
+    }
+
+    fn phase_time(&self, frame: u64) -> u64 {
+        frame.saturating_sub(self.phase_start)
+    }
+
+    // Number of cells a figure moves at a time
+    pub fn move_step(&self) -> i32 {
+        if self.config.big {
+            BIG_SCALE as i32
+        } else {
+            1
+        }
+    }
+
+    // Position of a figure after a move, if it fits there. The same rules
+    // as for the current figure, to let the computer player plan ahead.
+    pub fn try_move(&self, fig: &Figure, pos: Position, movement: Movement) -> Option<Position> {
+        self.try_move_on(&self.pf, fig, pos, movement)
     }
 
-    pub fn add_move(&mut self, movement: Movement, ticks: u64) {
-        self.move_queue.add_move(movement, ticks);
+    // Same as try_move but on another playfield, e.g. one with the figures
+    // before it already placed
+    pub fn try_move_on(
+        &self,
+        pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+        movement: Movement,
+    ) -> Option<Position> {
+        let step = self.move_step();
+        match movement {
+            Movement::RotateCW | Movement::RotateCCW => {
+                rotate_figure(self.rotation_system.as_ref(), pf, fig, pos, movement, step)
+            }
+            _ => {
+                // Big figures still fall one cell at a time to land on
+                // whatever height the stack has
+                let step = if movement == Movement::MoveDown {
+                    1
+                } else {
+                    step
+                };
+                let mut test_pos = pos;
+                for _ in 0..step {
+                    test_pos = Position::apply_move(&test_pos, movement);
+                    if fig.test_collision(pf, test_pos) {
+                        return None;
+                    }
+                }
+                Some(test_pos)
+            }
+        }
     }
 
-    fn execute_move(&mut self, movement: Movement) {
+    fn execute_move(&mut self, movement: Movement, frame: u64) {
         if let Some((fig, mut pos)) = self.current_figure.take() {
-            let test_pos = Position::apply_move(&pos, movement);
-            let collision = fig.test_collision(&self.pf, test_pos);
-            if collision && movement == Movement::MoveDown {
-                // Figure has landed
-                fig.place(&mut self.pf, pos);
-            } else {
-                if !collision {
-                    // Move was executed
-                    pos = test_pos;
+            if let Some(test_pos) = self.try_move(&fig, pos, movement) {
+                // Move was executed
+                pos = test_pos;
+                if self.phase == Phase::Locking {
+                    // Figure might have been moved off its landing spot
+                    if self.try_move(&fig, pos, Movement::MoveDown).is_some() {
+                        self.enter_phase(Phase::Falling, frame);
+                    }
                 }
-                self.current_figure = Some((fig, pos));
+            } else if movement == Movement::MoveDown && self.phase == Phase::Falling {
+                // Figure has landed
+                self.enter_phase(Phase::Locking, frame);
             }
+            self.current_figure = Some((fig, pos));
         }
     }
 
-    pub fn update(&mut self, ticks: u64) {
-        if self.game_over {
+    fn lock_figure(&mut self, frame: u64) {
+        if let Some((fig, pos)) = self.current_figure.take() {
+            fig.place(&mut self.pf, pos);
+            for (x, y, _) in fig.face(pos.dir()) {
+                self.set_block_lock_frame(
+                    i32::from(*x) + pos.x(),
+                    i32::from(*y) + pos.y(),
+                    Some(frame),
+                );
+            }
+            self.figures_placed += 1;
+        }
+        // Moves still queued were meant for the locked figure. Moves made
+        // from now on are kept for the next one.
+        self.move_queue.clear();
+        self.clearing_lines = self.pf.locked_lines();
+        self.clearing_lines.sort();
+        if self.clearing_lines.is_empty() {
+            self.enter_phase(Phase::Spawn, frame);
+        } else {
+            self.enter_phase(Phase::LineClear, frame);
+        }
+    }
+
+    fn fill_preview(&mut self) {
+        while self.preview.len() < self.config.preview.max(1) {
+            match self.figure_source.next_figure(&mut self.rng) {
+                Some(figure) => self.preview.push_back(figure),
+                None => break,
+            }
+        }
+    }
+
+    fn take_next_figure(&mut self) -> Option<Figure> {
+        let figure = self.preview.pop_front()?;
+        self.fill_preview();
+        Some(figure)
+    }
+
+    // Put a figure on hold and get the one held before, or the next
+    // figure if none was held
+    fn swap_held_figure(&mut self, figure: Figure) -> Option<Figure> {
+        self.hold_used = true;
+        match self.held_figure.replace(figure) {
+            Some(held_figure) => Some(held_figure),
+            None => self.take_next_figure(),
+        }
+    }
+
+    // Position a figure enters the playfield at
+    pub fn spawn_position(&self, figure: &Figure) -> Position {
+        // Big figures spawn on an even column to be able to reach both walls
+        let step = self.move_step();
+        let x = (self.pf.width() as i32 / 2 - step) / step * step;
+        Position::new((x, 0, self.rotation_system.spawn_dir(figure)))
+    }
+
+    // Place a figure at the top of the playfield. Returns false if there
+    // was no room for it, which ends the game.
+    fn enter_playfield(&mut self, figure: Option<Figure>, frame: u64) -> bool {
+        let figure = match figure {
+            Some(figure) => figure,
+            None => {
+                console_log!("Out of figures");
+                self.game_over = true;
+                return false;
+            }
+        };
+        let pos = self.spawn_position(&figure);
+        if figure.test_collision(&self.pf, pos) {
+            self.top_out(figure, frame);
+            return false;
+        }
+        self.current_figure = Some((figure, pos));
+        self.enter_phase(Phase::Falling, frame);
+        true
+    }
+
+    // Either end the game or clear rows to make room for the figure
+    fn top_out(&mut self, figure: Figure, frame: u64) {
+        let rows = match self.config.top_out {
+            TopOut::GameOver => {
+                console_log!("Game over");
+                self.game_over = true;
+                return;
+            }
+            TopOut::ClearRows(rows) => {
+                // At least down to the lowest block in the way of the
+                // figure, or it would still have no room
+                let pos = self.spawn_position(&figure);
+                let blocked_rows = figure
+                    .face(pos.dir())
+                    .iter()
+                    .map(|(x, y, _)| (i32::from(*x) + pos.x(), i32::from(*y) + pos.y()))
+                    .filter(|(x, y)| {
+                        let p = (*x, *y).into();
+                        self.pf.contains(p) && self.pf.get_block(p).is_set()
+                    })
+                    .map(|(_, y)| y as u32 + 1)
+                    .max()
+                    .unwrap_or(0);
+                rows.max(blocked_rows).min(self.pf.height())
+            }
+            TopOut::ClearBoard => self.pf.height(),
+        };
+        self.topped_out_figure = Some(figure);
+        self.current_figure = None;
+        self.clearing_lines = (0..rows).collect();
+        self.enter_phase(Phase::BoardClear, frame);
+    }
+
+    // Gravity acts on a new figure already on its first frame, which
+    // means a figure spawns on the ground at 20G
+    fn start_falling(&mut self, frame: u64) {
+        self.gravity_acc = self.gravity;
+        self.gravity_time = frame;
+        self.apply_gravity(frame);
+    }
+
+    fn spawn_figure(&mut self, frame: u64) {
+        self.hold_used = false;
+        let mut new_figure = match self.topped_out_figure.take() {
+            Some(figure) => Some(figure),
+            None => self.take_next_figure(),
+        };
+        if self.hold_buffered {
+            // Initial hold, swap the figure before it is even placed
+            self.hold_buffered = false;
+            new_figure = new_figure.and_then(|figure| self.swap_held_figure(figure));
+        }
+        if !self.enter_playfield(new_figure, frame) {
             return;
         }
-        if self.current_figure.is_some() {
-            let time_since_down = self.move_queue.time_since_move(ticks, Movement::MoveDown);
-            if time_since_down >= self.down_step_time as i64 {
-                // Let the figure fall
-                self.add_move(Movement::MoveDown, ticks);
+        self.take_snapshot(frame);
+
+        // Moves made while waiting for the figure act on it before
+        // gravity does, e.g. an initial rotation
+        while let Some(move_and_time) = self.move_queue.pop_next_move(frame) {
+            self.execute_move(move_and_time.movement, frame);
+        }
+        self.start_falling(frame);
+    }
+
+    //
+    // Swap the current figure with the one on hold (or the next figure if
+    // none is held). Only once per figure. Without a current figure the
+    // swap is done as the next figure spawns.
+    //
+    pub fn hold(&mut self) {
+        if self.game_over || self.hold_used || !self.config.hold {
+            return;
+        }
+        match self.current_figure.take() {
+            Some((figure, _)) => {
+                let new_figure = self.swap_held_figure(figure);
+                if self.enter_playfield(new_figure, self.frame) {
+                    self.start_falling(self.frame);
+                }
             }
-            // Execute enqueued moves
-            while let Some(move_and_time) = self.move_queue.pop_next_move(ticks) {
-                self.execute_move(move_and_time.movement);
+            None => self.hold_buffered = true,
+        }
+    }
+
+    fn take_snapshot(&mut self, frame: u64) {
+        let current_figure = match self.current_figure {
+            Some(ref current_figure) if self.config.undo_depth > 0 => current_figure.clone(),
+            _ => return,
+        };
+        // Keep the snapshot of the current figure plus one for each
+        // figure that can be undone
+        if self.snapshots.len() > self.config.undo_depth {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            pf: self.pf.clone(),
+            lock_frames: self.lock_frames.clone(),
+            figure_source: self.figure_source.clone(),
+            rng: self.rng.clone(),
+            preview: self.preview.clone(),
+            current_figure,
+            held_figure: self.held_figure.clone(),
+            hold_used: self.hold_used,
+            lines_cleared: self.lines_cleared,
+            figures_placed: self.figures_placed,
+            perfect_clears: self.perfect_clears,
+            score: self.score,
+            rise_interval: self.rise_interval,
+            rise_time_left: self.next_rise.saturating_sub(frame),
+        });
+    }
+
+    //
+    // Step back to when the last placed figure spawned, e.g. undo the
+    // current figure along with the one placed before it. Returns false
+    // if there is nothing to undo.
+    //
+    pub fn undo(&mut self) -> bool {
+        if self.current_figure.is_some() {
+            // Throw away the snapshot of the figure being played
+            if self.snapshots.len() < 2 {
+                return false;
             }
-        } else {
-            self.move_queue.clear();
+            self.snapshots.pop_back();
+        }
+        let snapshot = match self.snapshots.back() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.pf = snapshot.pf.clone();
+        self.lock_frames = snapshot.lock_frames.clone();
+        self.figure_source = snapshot.figure_source.clone();
+        self.rng = snapshot.rng.clone();
+        self.preview = snapshot.preview.clone();
+        self.current_figure = Some(snapshot.current_figure.clone());
+        self.held_figure = snapshot.held_figure.clone();
+        self.hold_used = snapshot.hold_used;
+        self.hold_buffered = false;
+        self.topped_out_figure = None;
+        self.lines_cleared = snapshot.lines_cleared;
+        self.figures_placed = snapshot.figures_placed;
+        self.perfect_clears = snapshot.perfect_clears;
+        self.score = snapshot.score;
+        self.rise_interval = snapshot.rise_interval;
+        self.next_rise = self.frame + snapshot.rise_time_left;
+        self.update_level();
+
+        // Play the figure from its spawn position again
+        self.clearing_lines.clear();
+        self.move_queue.clear();
+        self.game_over = false;
+        self.gravity_acc = 0.0;
+        self.gravity_time = self.frame;
+        self.enter_phase(Phase::Falling, self.frame);
+        true
+    }
 
+    // Let the figure fall for the time passed since gravity was last applied
+    fn apply_gravity(&mut self, frame: u64) {
+        let elapsed = frame.saturating_sub(self.gravity_time);
+        self.gravity_time = self.gravity_time.max(frame);
+        if self.phase != Phase::Falling {
+            // Nothing can fall while the figure rests on the ground
+            self.gravity_acc = 0.0;
+            return;
+        }
+        self.gravity_acc += self.gravity * elapsed as f32;
+        self.gravity_acc = self.gravity_acc.min(self.pf.height() as f32);
+        while self.gravity_acc >= 1.0 && self.phase == Phase::Falling {
+            self.gravity_acc -= 1.0;
+            self.execute_move(Movement::MoveDown, frame);
+        }
+        if self.phase != Phase::Falling {
+            self.gravity_acc = 0.0;
+        }
+    }
+
+    pub fn update(&mut self, frame: u64) {
+        self.frame = frame;
+        if self.game_over {
+            return;
+        }
+        self.update_rising_floor(frame);
+        if self.game_over {
+            return;
+        }
+        let phase_time = self.phase_time(frame);
+        if self.phase == Phase::LineClear && phase_time >= self.config.line_clear_delay {
             // Throw away full lines
-            let mut full_lines = self.pf.locked_lines();
-            full_lines.sort();
-            for line in &full_lines {
+            let width = self.pf.width() as usize;
+            for line in &self.clearing_lines {
                 self.pf.throw_line(*line);
+
+                // Lock frames follow the blocks moving down
+                let start = *line as usize * width;
+                self.lock_frames.drain(start..start + width);
+                self.lock_frames
+                    .splice(0..0, std::iter::repeat_n(None, width));
+            }
+            let lines = self.clearing_lines.len();
+            self.score += LINE_CLEAR_POINTS[lines.min(4)] * u64::from(self.level + 1);
+            self.lines_cleared += lines as u32;
+            self.update_level();
+            self.clearing_lines.clear();
+            if self
+                .pf
+                .blocks()
+                .row_iter()
+                .all(|row| row.iter().all(|b| !b.is_set()))
+            {
+                self.perfect_clears += 1;
             }
+            self.enter_phase(Phase::Spawn, frame);
+        }
+        if self.phase == Phase::BoardClear && phase_time >= self.config.line_clear_delay {
+            // Empty the rows without counting them as cleared lines
+            let width = self.pf.width();
+            for y in &self.clearing_lines {
+                for x in 0..width {
+                    self.pf
+                        .set_block((x as i32, *y as i32).into(), Block::Clear);
+                    self.lock_frames[(y * width + x) as usize] = None;
+                }
+            }
+            self.clearing_lines.clear();
+            self.enter_phase(Phase::Spawn, frame);
+        }
+        if self.phase == Phase::Spawn && self.phase_time(frame) >= self.config.entry_delay {
+            self.spawn_figure(frame);
+        }
+        if self.current_figure.is_some() {
+            // Execute enqueued moves in order, letting gravity act up to
+            // the time of each move
+            while let Some(move_and_time) = self.move_queue.pop_next_move(frame) {
+                let move_frame = move_and_time.time.max(self.gravity_time);
+                self.apply_gravity(move_frame);
+                self.execute_move(move_and_time.movement, move_frame);
+            }
+            self.apply_gravity(frame);
+            if self.phase == Phase::Locking && self.phase_time(frame) >= self.config.lock_delay {
+                self.lock_figure(frame);
+            }
+        }
+    }
+}
 
-            // Place the next figure
-            let new_figure = self.next_figure.clone();
-            let new_pos = Position::new(((self.pf.width() / 2 - 1) as i32, 0, 0));
-            if new_figure.test_collision(&self.pf, new_pos) {
-                console_log!("Game over");
-                self.game_over = true;
-            } else {
-                self.next_figure = Self::randomize_figure(&self.available_figures).clone();
-                self.current_figure = Some((new_figure, new_pos));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn test_game(pf: &str, figure_ids: &[usize]) -> Game {
+        let sequence = figure_ids.iter().map(|id| figure(*id)).collect();
+        let config = GameConfig {
+            gravity: 20.0,
+            lock_delay: 0,
+            line_clear_delay: 5,
+            entry_delay: 0,
+            rising_floor: None,
+            undo_depth: 2,
+            rotation: Rotation::Basic,
+            hold: true,
+            levels: None,
+            top_out: TopOut::GameOver,
+            big: false,
+            preview: 1,
+        };
+        sequence_game(pf, sequence, config)
+    }
+
+    fn run_frames(game: &mut Game, frames: std::ops::Range<u64>) {
+        for frame in frames {
+            game.update(frame);
+        }
+    }
+
+    #[test]
+    fn line_clear() {
+        let mut game = test_game("....\n....\n1..1\n1..1\n22.2\n", &[O]);
+
+        // Figure drops, locks and fills two lines right away
+        game.update(0);
+        assert_eq!(game.phase(), Phase::LineClear);
+        assert_eq!(game.clearing_lines(), &[2, 3]);
+        assert_eq!(game.dump_playfield(), "....\n....\n1441\n1441\n22.2\n");
+
+        // Lines stay until the line clear delay has passed
+        run_frames(&mut game, 1..5);
+        assert_eq!(game.phase(), Phase::LineClear);
+        assert_eq!(game.lines_cleared(), 0);
+
+        game.update(5);
+        assert_eq!(game.lines_cleared(), 2);
+        assert_eq!(game.figures_placed(), 1);
+        assert_eq!(game.perfect_clears(), 0);
+        assert!(game.clearing_lines().is_empty());
+        assert_eq!(game.dump_playfield(), "....\n....\n....\n....\n22.2\n");
+    }
+
+    #[test]
+    fn perfect_clear() {
+        let mut game = test_game("....\n....\n1..1\n1..1\n", &[O]);
+        run_frames(&mut game, 0..6);
+        assert_eq!(game.lines_cleared(), 2);
+        assert_eq!(game.perfect_clears(), 1);
+        assert_eq!(game.dump_playfield(), "....\n....\n....\n....\n");
+    }
+
+    #[test]
+    fn undo() {
+        let mut game = test_game("....\n....\n....\n....\n....\n....\n", &[O, O, O, O]);
+        assert!(!game.undo());
+
+        // Back to when the second figure spawned
+        run_frames(&mut game, 0..2);
+        assert_eq!(game.figures_placed(), 2);
+        assert!(game.undo());
+        assert_eq!(game.figures_placed(), 1);
+        assert_eq!(game.phase(), Phase::Falling);
+        assert_eq!(
+            game.dump_playfield(),
+            "....\n....\n....\n....\n.44.\n.44.\n"
+        );
+
+        // The figure can be played differently this time
+        game.add_move(Movement::MoveLeft, 2);
+        game.update(2);
+        assert_eq!(
+            game.dump_playfield(),
+            "....\n....\n44..\n44..\n.44.\n.44.\n"
+        );
+
+        // Game over can be undone as well
+        run_frames(&mut game, 3..5);
+        assert!(game.is_game_over());
+        assert!(game.undo());
+        assert!(!game.is_game_over());
+        assert_eq!(game.figures_placed(), 2);
+
+        // Only two figures back from the current one can be undone
+        assert!(game.undo());
+        assert!(game.undo());
+        assert_eq!(game.figures_placed(), 0);
+        assert!(!game.undo());
+    }
+
+    #[test]
+    fn buffered_moves() {
+        let mut game = test_game("....\n....\n....\n....\n", &[O, O]);
+        game.config.entry_delay = 2;
+        run_frames(&mut game, 0..3);
+        assert_eq!(game.dump_playfield(), "....\n....\n.44.\n.44.\n");
+
+        // Shift made during the entry delay is made as the figure spawns
+        game.add_move(Movement::MoveLeft, 3);
+        run_frames(&mut game, 3..5);
+        assert!(game.current_figure().is_none());
+        game.update(5);
+        assert_eq!(game.dump_playfield(), "44..\n44..\n.44.\n.44.\n");
+    }
+
+    #[test]
+    fn initial_rotation() {
+        let mut game = test_game("....\n....\n....\n....\n", &[1]);
+        game.config.entry_delay = 1;
+        game.config.lock_delay = 10;
+        game.add_move(Movement::RotateCW, 0);
+        game.update(0);
+        game.update(1);
+        match game.current_figure() {
+            Some((_, pos)) => assert_eq!(pos.dir(), 1),
+            None => panic!("No figure spawned"),
+        }
+    }
+
+    #[test]
+    fn initial_hold() {
+        let mut game = test_game("....\n....\n....\n....\n", &[O, 1, 7]);
+        game.config.entry_delay = 1;
+        game.config.lock_delay = 10;
+        game.hold();
+        game.update(0);
+        game.update(1);
+
+        // Held the first figure and got the second one
+        let t = figure(T);
+        assert!(matches!(game.current_figure(), Some((figure, _)) if *figure == t));
+
+        // Only one hold per figure
+        game.hold();
+        assert!(matches!(game.current_figure(), Some((figure, _)) if *figure == t));
+    }
+
+    #[test]
+    fn classic_levels() {
+        let config = GameConfig::nes_classic(0);
+        let levels = config.levels.as_ref().unwrap();
+        assert_eq!(levels.level(9), 0);
+        assert_eq!(levels.level(10), 1);
+        assert_eq!(levels.level(25), 2);
+        assert_eq!(levels.gravity(0), 1.0 / 48.0);
+        assert_eq!(levels.gravity(40), 1.0);
+
+        // Starting at a higher level takes more lines for the first level
+        // up
+        let config = GameConfig::nes_classic(18);
+        let levels = config.levels.as_ref().unwrap();
+        assert_eq!(levels.first_level_lines, 130);
+        assert_eq!(levels.level(129), 18);
+        assert_eq!(levels.level(130), 19);
+        assert_eq!(levels.level(140), 20);
+        assert_eq!(
+            GameConfig::nes_classic(9).levels.unwrap().first_level_lines,
+            100
+        );
+    }
+
+    #[test]
+    fn classic_scoring() {
+        let mut game = test_game("....\n....\n1..1\n1..1\n22.2\n", &[O]);
+        game.config.levels = Some(Levels {
+            start_level: 3,
+            frames_per_row: vec![1],
+            first_level_lines: 1,
+            level_lines: 10,
+        });
+        game.update_level();
+        assert_eq!(game.level(), 3);
+
+        // Two lines at level 3, then one level up
+        run_frames(&mut game, 0..10);
+        assert_eq!(game.score(), 100 * 4);
+        assert_eq!(game.level(), 4);
+    }
+
+    #[test]
+    fn zen_top_out() {
+        let mut game = test_game("....\n....\n....\n1...\n", &[O, O, O, O]);
+        game.config.top_out = TopOut::ClearRows(3);
+        run_frames(&mut game, 0..3);
+
+        // Third figure has no room, the top rows are cleared instead
+        assert!(!game.is_game_over());
+        assert_eq!(game.phase(), Phase::BoardClear);
+        assert_eq!(game.clearing_lines(), &[0, 1, 2]);
+        run_frames(&mut game, 3..7);
+        assert_eq!(game.dump_playfield(), ".44.\n.44.\n.44.\n144.\n");
+
+        // The figure is played once there is room and stats keep counting
+        game.update(7);
+        assert_eq!(game.dump_playfield(), "....\n.44.\n.44.\n144.\n");
+        assert_eq!(game.figures_placed(), 3);
+        assert_eq!(game.lines_cleared(), 0);
+        assert!(!game.is_game_over());
+    }
+
+    #[test]
+    fn zen_top_out_single_row() {
+        let mut game = test_game("....\n....\n....\n1...\n", &[O, O, O, O, O]);
+        game.config.top_out = TopOut::ClearRows(1);
+        run_frames(&mut game, 0..3);
+
+        // The figure is blocked by two rows, so both are cleared
+        assert_eq!(game.phase(), Phase::BoardClear);
+        assert_eq!(game.clearing_lines(), &[0, 1]);
+        run_frames(&mut game, 3..8);
+        assert_eq!(game.dump_playfield(), ".44.\n.44.\n.44.\n144.\n");
+        assert_eq!(game.figures_placed(), 3);
+
+        // And again for the next figure
+        run_frames(&mut game, 8..16);
+        assert_eq!(game.figures_placed(), 4);
+        assert!(!game.is_game_over());
+    }
+
+    #[test]
+    fn preview() {
+        let figures = crate::init_figures();
+        let config = GameConfig {
+            preview: 3,
+            ..quick_config()
+        };
+        let mut game = sequence_game("....\n....\n....\n....\n", figures.clone(), config);
+        game.update(0);
+        assert_eq!(game.current_figure().as_ref().unwrap().0, figures[0]);
+        assert_eq!(*game.preview(), figures[1..4].to_vec());
+        assert_eq!(game.next_figure(), Some(&figures[1]));
+    }
+
+    #[test]
+    fn big_figures() {
+        let config = GameConfig {
+            gravity: 20.0,
+            lock_delay: 0,
+            big: true,
+            ..quick_config()
+        };
+        let mut game = sequence_game(&"........\n".repeat(8), vec![figure(O); 2], config);
+
+        // Figures cover 2x2 cells per block and move two cells sideways
+        game.add_move(Movement::MoveLeft, 0);
+        game.update(0);
+        assert_eq!(
+            game.dump_playfield(),
+            "........\n".repeat(4) + "4444....\n4444....\n4444....\n4444....\n"
+        );
+        game.add_move(Movement::MoveRight, 1);
+        game.update(1);
+        assert_eq!(game.clearing_lines(), &[4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn block_lock_frames() {
+        let mut game = test_game("....\n....\n....\n1..1\n", &[O]);
+        game.update(0);
+        assert_eq!(game.block_lock_frame(1, 2), Some(0));
+        assert_eq!(game.block_lock_frame(2, 3), Some(0));
+        assert_eq!(game.block_lock_frame(0, 3), None);
+        assert_eq!(game.block_lock_frame(0, 0), None);
+
+        // Line clear moves the lock frames down with the blocks
+        run_frames(&mut game, 1..6);
+        assert_eq!(game.dump_playfield(), "....\n....\n....\n.44.\n");
+        assert_eq!(game.block_lock_frame(1, 3), Some(0));
+        assert_eq!(game.block_lock_frame(1, 2), None);
+
+        // Garbage pushes them up
+        game.push_garbage_row(0);
+        assert_eq!(game.block_lock_frame(1, 2), Some(0));
+        assert_eq!(game.block_lock_frame(1, 3), None);
+    }
+
+    #[test]
+    fn garbage_rows() {
+        let mut game = test_game("....\n....\n..1.\n", &[O]);
+        game.push_garbage_row(1);
+        assert_eq!(game.dump_playfield(), "....\n..1.\n8.88\n");
+        assert_eq!(game.garbage_rows(), 1);
+
+        game.push_garbage_row(3);
+        assert_eq!(game.dump_playfield(), "..1.\n8.88\n888.\n");
+        assert_eq!(game.garbage_rows(), 2);
+        assert!(!game.is_game_over());
+
+        // Blocks pushed out over the top
+        game.push_garbage_row(0);
+        assert!(game.is_game_over());
+    }
+
+    #[test]
+    fn rising_floor() {
+        let config = GameConfig {
+            rising_floor: Some(RisingFloor {
+                start_interval: 10,
+                min_interval: 4,
+                interval_step: 3,
+            }),
+            ..GameConfig::default()
+        };
+        let mut game = sequence_game(&"....\n".repeat(10), vec![], config);
+        assert_eq!(game.garbage_rise_rate(), 0.1);
+
+        // Rows rise faster and faster until the minimum interval
+        let mut rise_frames = vec![];
+        for frame in 0..30 {
+            let rows = game.garbage_rows();
+            game.update_rising_floor(frame);
+            if game.garbage_rows() > rows {
+                rise_frames.push(frame);
             }
         }
+        assert_eq!(rise_frames, vec![10, 17, 21, 25, 29]);
+        assert_eq!(game.garbage_rise_rate(), 0.25);
+    }
+
+    #[test]
+    fn no_line_clear() {
+        let mut game = test_game("....\n....\n....\n1...\n", &[O, O, O]);
+        game.update(0);
+        assert_eq!(game.phase(), Phase::Spawn);
+        assert!(game.clearing_lines().is_empty());
+
+        // Third figure has no room left
+        run_frames(&mut game, 1..3);
+        assert!(game.is_game_over());
+        assert_eq!(game.figures_placed(), 2);
+        assert_eq!(game.lines_cleared(), 0);
+        assert_eq!(game.dump_playfield(), ".44.\n.44.\n.44.\n144.\n");
     }
 }
@@ -0,0 +1,26 @@
+use wasm_bindgen::prelude::*;
+
+use crate::game::Game;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+//
+// A game mode adds a goal (and possibly extra rules) on top of a game.
+//
+pub trait GameMode {
+    // Called every frame before the game is updated
+    fn update(&mut self, _game: &mut Game, _frame: u64) {}
+
+    fn status(&self, game: &Game) -> ModeStatus;
+
+    // Ranked modes don't allow figures to be undone
+    fn allows_undo(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,52 @@
+use rstris::playfield::Playfield;
+
+use crate::computer_player::*;
+use crate::game::*;
+
+// Result of a game played without drawing it
+#[derive(Debug, Clone, Copy)]
+pub struct Outcome {
+    pub lines_cleared: u32,
+    pub figures_placed: u32,
+    pub frames: u64,
+    // False if the game was stopped before topping out
+    pub game_over: bool,
+}
+
+// Rules for headless games, the same as for a game in the browser
+pub fn headless_config() -> GameConfig {
+    GameConfig {
+        gravity: 0.5,
+        lock_delay: 4,
+        line_clear_delay: 9,
+        entry_delay: 2,
+        preview: 5,
+        ..GameConfig::default()
+    }
+}
+
+// Create a game on an empty playfield with the standard figures
+pub fn new_game(width: u32, height: u32, config: GameConfig, seed: u64) -> Game {
+    let pf = Playfield::new("Headless", width, height);
+    Game::new_seeded(pf, crate::init_figures(), config, seed)
+}
+
+// Let the computer player play a game until it tops out or has placed the
+// given number of figures
+pub fn play_game<T>(game: &mut Game, player: &mut ComputerPlayer<T>, max_figures: u32) -> Outcome
+where
+    T: ComputerType,
+{
+    let mut frame = 0;
+    while !game.is_game_over() && game.figures_placed() < max_figures {
+        player.act_on_game(game, frame);
+        game.update(frame);
+        frame += 1;
+    }
+    Outcome {
+        lines_cleared: game.lines_cleared(),
+        figures_placed: game.figures_placed(),
+        frames: frame,
+        game_over: game.is_game_over(),
+    }
+}
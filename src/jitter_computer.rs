@@ -4,7 +4,7 @@ use rstris::position::Position;
 
 use crate::computer_player::*;
 
-fn get_pf_row_jitter(pf: &Playfield) -> u32 {
+pub fn get_pf_row_jitter(pf: &Playfield) -> u32 {
     let mut jitter = 0;
     for row in pf.blocks().row_iter() {
         // For each row...
@@ -23,7 +23,7 @@ fn get_pf_row_jitter(pf: &Playfield) -> u32 {
     }
     jitter
 }
-fn get_pf_col_jitter(pf: &Playfield) -> u32 {
+pub fn get_pf_col_jitter(pf: &Playfield) -> u32 {
     let mut jitter = 0;
     for x in 0..(pf.width() as i32) {
         // For each column...
@@ -54,6 +54,16 @@ fn get_pf_max_height(pf: &Playfield) -> u32 {
     0
 }
 
+// Height of the stack, counted from the bottom to its highest block
+fn get_pf_stack_height(pf: &Playfield) -> u32 {
+    for (y, row) in pf.blocks().row_iter().enumerate() {
+        if row.iter().any(|b| b.is_set()) {
+            return pf.height() - y as u32;
+        }
+    }
+    0
+}
+
 fn get_pf_avg_height(pf: &Playfield) -> f32 {
     let mut total_height = 0;
     for x in 0..(pf.width() as i32) {
@@ -75,6 +85,7 @@ pub struct JitterComputer {
     avg_height_factor: f32,
     pre_max_height: u32,
     pre_locked_lines: i32,
+    garbage_pressure: f32,
     pf: Option<Playfield>,
 }
 impl JitterComputer {
@@ -87,10 +98,75 @@ impl JitterComputer {
             avg_height_factor: 0.0,
             pre_max_height: 0,
             pre_locked_lines: 0,
+            garbage_pressure: 0.0,
+        }
+    }
+}
+impl JitterComputer {
+    // Terms of the evaluation of a placing, adding up to it
+    fn placing_terms(
+        &mut self,
+        current_pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+    ) -> [(&'static str, f32); 5] {
+        let pf = self.pf.get_or_insert_with(|| current_pf.clone());
+        pf.copy(current_pf);
+        fig.place(pf, pos);
+        let mut full_lines = pf.locked_lines();
+        full_lines.sort();
+
+        let full_lines_score = if full_lines.len() >= 4 {
+            // Great things!
+            10.0
+        } else if full_lines.len() == 1 {
+            // Single full line - Not too bad but still a bit unnecessary
+            -2.0
+        } else if full_lines.len() >= 2 {
+            // 2 or 3 lines should be avoided as long as the avarage playfield height is low
+            let factor = 1.0 - (self.pre_avg_height / pf.height() as f32);
+            (4 - full_lines.len()) as f32 * -factor * 3.0
+        } else {
+            // No full lines - Don't care
+            0.0
+        };
+
+        for line in &full_lines {
+            pf.throw_line(*line);
         }
+
+        let bottom_block = (i32::from(fig.lowest_block(pos.dir())) + pos.y()) / 2;
+
+        // Measure playfield jitter. Lower jitter is better.
+        let col_jitter = get_pf_col_jitter(pf) as i32 - self.pre_col_jitter;
+        let row_jitter = get_pf_row_jitter(pf) as i32 - self.pre_row_jitter;
+
+        // With garbage rising from below every row of stack height gets
+        // more dangerous. Rows risen per figure are few compared to the
+        // playfield, so weigh them by its height.
+        let stack_height = get_pf_stack_height(pf);
+        let pressure_score = -self.garbage_pressure * (stack_height * pf.height()) as f32;
+
+        [
+            ("depth", bottom_block as f32),
+            ("col_jitter", -(col_jitter * 3) as f32),
+            ("row_jitter", -(row_jitter / 2) as f32),
+            ("full_lines", full_lines_score),
+            ("garbage_pressure", pressure_score),
+        ]
+    }
+}
+
+impl Default for JitterComputer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 impl ComputerType for JitterComputer {
+    fn set_garbage_pressure(&mut self, rows: f32) {
+        self.garbage_pressure = rows;
+    }
+
     fn init_eval(&mut self, pf: &Playfield, _: usize) {
         if self.pf.is_none() {
             self.pf = Some(pf.clone())
@@ -104,41 +180,44 @@ impl ComputerType for JitterComputer {
     }
 
     fn eval_placing(&mut self, current_pf: &Playfield, fig: &Figure, pos: Position) -> f32 {
-        if let Some(ref mut pf) = self.pf {
-            pf.copy(current_pf);
-            fig.place(pf, pos);
-            let mut full_lines = pf.locked_lines();
-            full_lines.sort();
-
-            let full_lines_score = if full_lines.len() >= 4 {
-                // Great things!
-                10.0
-            } else if full_lines.len() == 1 {
-                // Single full line - Not too bad but still a bit unnecessary
-                -2.0
-            } else if full_lines.len() >= 2 {
-                // 2 or 3 lines should be avoided as long as the avarage playfield height is low
-                let factor = 1.0 - (self.pre_avg_height / pf.height() as f32);
-                (4 - full_lines.len()) as f32 * -factor * 3.0
-            } else {
-                // No full lines - Don't care
-                0.0
-            };
-
-            for line in &full_lines {
-                pf.throw_line(*line);
-            }
+        self.placing_terms(current_pf, fig, pos)
+            .iter()
+            .map(|(_, value)| value)
+            .sum()
+    }
+
+    fn explain_placing(
+        &mut self,
+        current_pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+    ) -> Vec<(&'static str, f32)> {
+        self.placing_terms(current_pf, fig, pos).to_vec()
+    }
+}
 
-            let bottom_block = (i32::from(fig.lowest_block(pos.dir())) + pos.y()) / 2;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer_player::ComputerPlayer;
+    use crate::test_helpers::*;
 
-            // Measure playfield jitter. Lower jitter is better.
-            let col_jitter = get_pf_col_jitter(pf) as i32 - self.pre_col_jitter;
-            let row_jitter = get_pf_row_jitter(pf) as i32 - self.pre_row_jitter;
-            let jitter_score = -(col_jitter * 3 + row_jitter / 2);
+    #[test]
+    fn garbage_pressure() {
+        let pf = "......\n".repeat(6) + "1.....\n1.....\n1....1\n11..11\n";
+        let hint = |pressure| {
+            let mut game = sequence_game(&pf, vec![figure(I); 2], quick_config());
+            game.update(0);
+            let mut com_type = JitterComputer::new();
+            com_type.set_garbage_pressure(pressure);
+            let mut player = ComputerPlayer::new(2.0, com_type);
+            player.set_lookahead(false);
+            player.hint(&game).unwrap().pos
+        };
 
-            (bottom_block + jitter_score) as f32 + full_lines_score
-        } else {
-            0.0
-        }
+        // A lower placing is preferred when garbage is rising
+        let calm = hint(0.0);
+        let pressed = hint(0.1);
+        assert!(pressed.y() > calm.y());
     }
 }
@@ -1,23 +1,48 @@
 #[macro_use]
 mod utils;
 
-mod computer_player;
+mod ascii_playfield;
+pub mod beam_search;
+pub mod computer_player;
+pub mod computer_registry;
+pub mod difficulty;
+mod dig_race;
 
 mod draw;
-mod game;
-mod jitter_computer;
+mod fumen;
+pub mod game;
+mod game_mode;
+pub mod headless;
+pub mod jitter_computer;
+mod puzzle;
+pub mod rollout_computer;
+pub mod rotation_system;
+mod survival;
+#[cfg(test)]
+mod test_helpers;
+pub mod weighted_computer;
 
 use rstris::block::*;
 use rstris::figure::*;
+use rstris::movement::Movement;
 use rstris::playfield::Playfield;
 
 use std::f64;
 use wasm_bindgen::prelude::*;
 
+use crate::beam_search::*;
 use crate::computer_player::*;
+use crate::computer_registry::*;
+use crate::difficulty::*;
+use crate::dig_race::*;
+use crate::fumen::*;
 use crate::game::*;
+use crate::game_mode::*;
 
 use crate::jitter_computer::*;
+use crate::puzzle::*;
+use crate::rotation_system::*;
+use crate::survival::*;
 use crate::utils::*;
 
 macro_rules! bl {
@@ -32,7 +57,7 @@ macro_rules! bl {
 //
 // Build list of figures
 //
-fn init_figures() -> Vec<Figure> {
+pub fn init_figures() -> Vec<Figure> {
     vec![
         Figure::new_from_face(
             "1",
@@ -73,11 +98,57 @@ fn init_figures() -> Vec<Figure> {
     ]
 }
 
+// The game is simulated in fixed steps of this length (ms)
+const FRAME_TIME: f64 = 1000.0 / 60.0;
+
+// Max number of frames to catch up on in a single update. Any time
+// beyond that is dropped rather than fast forwarding the game.
+const MAX_CATCH_UP_FRAMES: u32 = 10;
+
+// Color of the outline showing where the computer would place the figure
+const HINT_COLOR: (f32, f32, f32, f32) = (0.9, 0.9, 0.9, 1.0);
+
+// Moves of a human player
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Left,
+    Right,
+    Down,
+    RotateCw,
+    RotateCcw,
+}
+
+// How locked blocks are shown. The game itself plays the same either way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockVisibility {
+    Visible,
+    // Blocks fade out over a number of frames, starting a delay after
+    // being locked
+    Fading { delay: u64, duration: u64 },
+    Invisible,
+}
+
 #[wasm_bindgen]
 pub struct GameContext {
     game: Game,
-    computer_player: ComputerPlayer<JitterComputer>,
+    computer_player: ComputerPlayer<Box<dyn ComputerType>>,
     draw: draw::Draw,
+
+    // Let the computer player play, otherwise the game is played by input
+    autoplay: bool,
+    // Outline where the computer player would place the figure
+    hint: bool,
+
+    // Set when playing with a goal, e.g. a puzzle
+    mode: Option<Box<dyn GameMode>>,
+
+    block_visibility: BlockVisibility,
+
+    // Current frame and the time not yet simulated
+    frame: u64,
+    frame_time_acc: f64,
+    last_update_time: Option<f64>,
 }
 
 #[wasm_bindgen]
@@ -87,17 +158,385 @@ impl GameContext {
         let figure_list = init_figures();
         let pf = Playfield::new("Playfield 1", width, height);
         console_log!("Create game context (draw on: {})", canvas_id);
+        let game = Game::new(pf, figure_list, Self::game_config());
+        Self::with_game(canvas_id, game, None)
+    }
+
+    // Create a game context racing to clear rows of garbage. The same seed
+    // gives the same garbage and figures.
+    pub fn new_dig_race(
+        canvas_id: &str,
+        width: u32,
+        height: u32,
+        garbage_rows: u32,
+        seed: u32,
+    ) -> Self {
+        set_panic_hook();
+        let pf = Playfield::new("Playfield 1", width, height);
+        console_log!("Create dig race context (draw on: {})", canvas_id);
+        let dig_race = DigRace::new(garbage_rows);
+        let game = dig_race.start(pf, init_figures(), Self::game_config(), u64::from(seed));
+        Self::with_game(canvas_id, game, Some(Box::new(dig_race)))
+    }
+
+    // Create a game context where garbage rows rise from the bottom at an
+    // increasing pace. The score is the time survived.
+    pub fn new_survival(canvas_id: &str, width: u32, height: u32) -> Self {
+        set_panic_hook();
+        let pf = Playfield::new("Playfield 1", width, height);
+        console_log!("Create survival context (draw on: {})", canvas_id);
+        let survival = Survival::new(RisingFloor {
+            start_interval: 600,
+            min_interval: 60,
+            interval_step: 20,
+        });
+        let game = survival.start(pf, init_figures(), Self::game_config());
+        Self::with_game(canvas_id, game, Some(Box::new(survival)))
+    }
+
+    //
+    // Create a game context playing a puzzle. The playfield is given as
+    // block ids row by row (0 for empty), figures as a list of figure ids
+    // to play and the goal as "clear-lines:<count>" or "perfect-clear".
+    //
+    pub fn new_puzzle(
+        canvas_id: &str,
+        width: u32,
+        height: u32,
+        blocks: &[u8],
+        figures: &[u8],
+        goal: &str,
+    ) -> Result<GameContext, JsValue> {
+        set_panic_hook();
+        let goal: PuzzleGoal = goal.parse().map_err(|e: String| JsValue::from(e))?;
+        let pf = Puzzle::playfield_from_blocks(width, height, blocks)?;
+        let puzzle = Puzzle::new(pf, figures, &init_figures(), goal)?;
+        console_log!("Create puzzle context (draw on: {})", canvas_id);
+        let game = puzzle.start(Self::game_config());
+        Ok(Self::with_game(canvas_id, game, Some(Box::new(puzzle))))
+    }
+
+    //
+    // Create a game context playing a puzzle from a fumen (10 blocks
+    // wide). The first page gives the playfield and all pages together
+    // give the figures to play.
+    //
+    pub fn new_fumen_puzzle(
+        canvas_id: &str,
+        height: u32,
+        fumen: &str,
+        goal: &str,
+    ) -> Result<GameContext, JsValue> {
+        set_panic_hook();
+        let goal: PuzzleGoal = goal.parse().map_err(|e: String| JsValue::from(e))?;
+        let puzzle = Puzzle::from_fumen(fumen, height, &init_figures(), goal)?;
+        console_log!("Create fumen puzzle context (draw on: {})", canvas_id);
+        let game = puzzle.start(Self::game_config());
+        Ok(Self::with_game(canvas_id, game, Some(Box::new(puzzle))))
+    }
+
+    //
+    // Create a game context that never ends. Whenever there is no room for
+    // a new figure the given number of rows are cleared from the top, or
+    // the whole playfield if 0.
+    //
+    pub fn new_zen(canvas_id: &str, width: u32, height: u32, clear_rows: u32) -> Self {
+        set_panic_hook();
+        let pf = Playfield::new("Playfield 1", width, height);
+        console_log!("Create zen context (draw on: {})", canvas_id);
+        let top_out = if clear_rows == 0 {
+            TopOut::ClearBoard
+        } else {
+            TopOut::ClearRows(clear_rows)
+        };
+        let config = GameConfig {
+            top_out,
+            ..Self::game_config()
+        };
+        let game = Game::new(pf, init_figures(), config);
+        Self::with_game(canvas_id, game, None)
+    }
+
+    // Create a game context where figures are twice the size and move two
+    // cells at a time
+    pub fn new_big(canvas_id: &str, width: u32, height: u32) -> Self {
+        set_panic_hook();
+        let pf = Playfield::new("Playfield 1", width, height);
+        console_log!("Create big context (draw on: {})", canvas_id);
+        let config = GameConfig {
+            big: true,
+            ..Self::game_config()
+        };
+        let game = Game::new(pf, init_figures(), config);
+        Self::with_game(canvas_id, game, None)
+    }
+
+    // Create a game context playing by the rules of NES Tetris, starting
+    // at the given level
+    pub fn new_classic(canvas_id: &str, width: u32, height: u32, start_level: u32) -> Self {
+        set_panic_hook();
+        let pf = Playfield::new("Playfield 1", width, height);
+        console_log!("Create classic context (draw on: {})", canvas_id);
+        let game = Game::new(pf, init_figures(), GameConfig::nes_classic(start_level));
+        Self::with_game(canvas_id, game, None)
+    }
+
+    fn game_config() -> GameConfig {
+        GameConfig {
+            gravity: 0.5,
+            lock_delay: 4,
+            line_clear_delay: 9,
+            entry_delay: 2,
+            rising_floor: None,
+            undo_depth: 16,
+            rotation: Rotation::Basic,
+            hold: true,
+            levels: None,
+            top_out: TopOut::GameOver,
+            big: false,
+            preview: 5,
+        }
+    }
+
+    fn with_game(canvas_id: &str, game: Game, mode: Option<Box<dyn GameMode>>) -> Self {
+        let pf = game.playfield();
+        let mut draw = draw::Draw::new(canvas_id, pf.width(), pf.height());
+        let (r, g, b, _) = Self::block_color(0);
+        draw.set_background((r, g, b));
         GameContext {
-            game: Game::new(pf, figure_list, 10),
-            computer_player: ComputerPlayer::new(2.0, JitterComputer::new()),
-            draw: draw::Draw::new(canvas_id, width, height),
+            game,
+            computer_player: ComputerPlayer::new(2.0, Box::new(JitterComputer::new())),
+            draw,
+            autoplay: true,
+            hint: false,
+            mode,
+            block_visibility: BlockVisibility::Visible,
+            frame: 0,
+            frame_time_acc: 0.0,
+            last_update_time: None,
+        }
+    }
+
+    // Status of the game mode being played, if any
+    pub fn mode_status(&self) -> Option<ModeStatus> {
+        self.mode.as_ref().map(|mode| mode.status(&self.game))
+    }
+
+    // Seconds played, which stops counting when a game mode is finished
+    pub fn play_time(&self) -> f64 {
+        self.frame as f64 * FRAME_TIME / 1000.0
+    }
+
+    // Advance the game as many frames as fits in the time (ms) passed
+    // since last update
+    pub fn update(&mut self, time: f64) {
+        if let Some(last_time) = self.last_update_time {
+            self.frame_time_acc += (time - last_time).max(0.0);
+        }
+        self.last_update_time = Some(time);
+
+        let mut frames = 0;
+        while self.frame_time_acc >= FRAME_TIME {
+            if frames == MAX_CATCH_UP_FRAMES {
+                self.frame_time_acc = 0.0;
+                break;
+            }
+            self.frame_time_acc -= FRAME_TIME;
+            self.step();
+            frames += 1;
+        }
+    }
+
+    pub fn lines_cleared(&self) -> u32 {
+        self.game.lines_cleared()
+    }
+
+    pub fn figures_placed(&self) -> u32 {
+        self.game.figures_placed()
+    }
+
+    pub fn score(&self) -> u64 {
+        self.game.score()
+    }
+
+    pub fn level(&self) -> u32 {
+        self.game.level()
+    }
+
+    // Playfield in text form ('.' for empty blocks and block ids for set)
+    pub fn dump_playfield(&self) -> String {
+        self.game.dump_playfield()
+    }
+
+    pub fn load_playfield(&mut self, text: &str) -> Result<(), JsValue> {
+        self.game.load_playfield(text)?;
+        Ok(())
+    }
+
+    // Current playfield and figure as a fumen (needs a 10 blocks wide
+    // playfield)
+    pub fn export_fumen(&self) -> Result<String, JsValue> {
+        let fumen = encode_fumen(self.game.playfield(), self.game.current_figure().as_ref())?;
+        Ok(fumen)
+    }
+
+    // Advance the game exactly one frame
+    pub fn step(&mut self) {
+        if let Some(ref mut mode) = self.mode {
+            if mode.status(&self.game) != ModeStatus::InProgress {
+                // Game mode is finished
+                return;
+            }
+            mode.update(&mut self.game, self.frame);
+        }
+        if self.autoplay {
+            self.computer_player.act_on_game(&mut self.game, self.frame);
+        }
+        self.game.update(self.frame);
+        self.frame += 1;
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Select the rotation system, e.g. Rotation.Srs
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.game.set_rotation(rotation);
+    }
+
+    // Let the computer player search placings of the previewed figures,
+    // keeping the given number of boards at each of depth figures. A
+    // depth of 0 goes back to only looking at the next figure.
+    pub fn set_beam_search(&mut self, width: usize, depth: usize) {
+        let beam_search = if depth == 0 {
+            None
+        } else {
+            Some(BeamSearch { width, depth })
+        };
+        self.computer_player.set_beam_search(beam_search);
+    }
+
+    // Make the computer player think, move and make mistakes like a
+    // player of the given skill. The mistakes are picked by the seed.
+    pub fn set_ai_difficulty(&mut self, difficulty: AiDifficulty, seed: u32) {
+        self.computer_player
+            .set_difficulty(difficulty.settings(), u64::from(seed));
+    }
+
+    //
+    // Every placing the computer player considers for the current figure,
+    // best first, as { x, y, dir, eval, terms } objects. The terms object
+    // holds the named parts adding up to eval.
+    //
+    pub fn explain_placings(&mut self) -> Result<js_sys::Array, JsValue> {
+        let placings = js_sys::Array::new();
+        for explanation in self.computer_player.explain(&self.game) {
+            let terms = js_sys::Object::new();
+            for (name, value) in &explanation.terms {
+                js_sys::Reflect::set(&terms, &JsValue::from(*name), &JsValue::from(*value))?;
+            }
+            let pos = explanation.pos;
+            let placing = js_sys::Object::new();
+            js_sys::Reflect::set(&placing, &"x".into(), &pos.x().into())?;
+            js_sys::Reflect::set(&placing, &"y".into(), &pos.y().into())?;
+            js_sys::Reflect::set(&placing, &"dir".into(), &pos.dir().into())?;
+            js_sys::Reflect::set(&placing, &"eval".into(), &explanation.eval.into())?;
+            js_sys::Reflect::set(&placing, &"terms".into(), &terms)?;
+            placings.push(&placing);
+        }
+        Ok(placings)
+    }
+
+    // Let the computer player rate placings by weighted features, with
+    // weights as written by the trainer (one 'name weight' per line)
+    pub fn load_weights(&mut self, text: &str) -> Result<(), JsValue> {
+        self.set_ai_with_params("weighted", text)
+    }
+
+    // Let the computer player rate placings by the named computer type,
+    // e.g. "jitter" or "el-tetris", from the next figure on
+    pub fn set_ai(&mut self, name: &str) -> Result<(), JsValue> {
+        self.set_ai_with_params(name, "")
+    }
+
+    // Same as set_ai, with parameters as one 'name value' per line
+    pub fn set_ai_with_params(&mut self, name: &str, params: &str) -> Result<(), JsValue> {
+        let com_type = new_computer_type(name, params)?;
+        self.computer_player.set_com_type(com_type);
+        Ok(())
+    }
+
+    // Names of the computer types set_ai takes
+    pub fn ai_names() -> js_sys::Array {
+        COMPUTER_TYPES
+            .iter()
+            .map(|name| JsValue::from(*name))
+            .collect()
+    }
+
+    // Let the computer player play, or leave the game to input
+    pub fn set_autoplay(&mut self, autoplay: bool) {
+        if autoplay && !self.autoplay {
+            // Plan from where the figure is now
+            self.computer_player.forget_figure();
+        }
+        self.autoplay = autoplay;
+    }
+
+    // Move the current figure on the next frame
+    pub fn input(&mut self, input: Input) {
+        let movement = match input {
+            Input::Left => Movement::MoveLeft,
+            Input::Right => Movement::MoveRight,
+            Input::Down => Movement::MoveDown,
+            Input::RotateCw => Movement::RotateCW,
+            Input::RotateCcw => Movement::RotateCCW,
+        };
+        self.game.add_move(movement, self.frame);
+    }
+
+    // Show an outline where the computer player would place the figure
+    pub fn set_hint(&mut self, hint: bool) {
+        self.hint = hint;
+    }
+
+    // Swap the current figure with the held one. Before the next figure
+    // has spawned it is swapped as it spawns.
+    pub fn hold(&mut self) {
+        self.game.hold();
+    }
+
+    // Step back to when the last placed figure spawned. Not available in
+    // ranked modes. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        if self.mode.as_ref().is_some_and(|mode| !mode.allows_undo()) {
+            return false;
+        }
+        if !self.game.undo() {
+            return false;
         }
+        self.computer_player.forget_figure();
+        true
     }
 
-    pub fn update(&mut self, ticks: f64) {
-        let ticks: u64 = (ticks.round() as i64) as u64;
-        self.computer_player.act_on_game(&mut self.game, ticks);
-        self.game.update(ticks);
+    // Show locked blocks as usual
+    pub fn set_visible_blocks(&mut self) {
+        self.block_visibility = BlockVisibility::Visible;
+    }
+
+    // Let locked blocks fade out over a number of frames, starting a
+    // number of frames after they were locked
+    pub fn set_fading_blocks(&mut self, delay: u32, duration: u32) {
+        self.block_visibility = BlockVisibility::Fading {
+            delay: u64::from(delay),
+            duration: u64::from(duration),
+        };
+    }
+
+    // Hide blocks as soon as they are locked
+    pub fn set_invisible_blocks(&mut self) {
+        self.block_visibility = BlockVisibility::Invisible;
     }
 
     fn block_color(id: u8) -> (f32, f32, f32, f32) {
@@ -114,18 +553,63 @@ impl GameContext {
         }
     }
 
+    // Fade a block color towards white as the line clear progresses
+    fn clearing_color(color: (f32, f32, f32, f32), progress: f32) -> (f32, f32, f32, f32) {
+        let (r, g, b, a) = color;
+        (
+            r + (1.0 - r) * progress,
+            g + (1.0 - g) * progress,
+            b + (1.0 - b) * progress,
+            a,
+        )
+    }
+
+    // Opacity of a block locked at the given frame. Blocks that were not
+    // locked by a figure (e.g. garbage) are always shown.
+    fn block_alpha(&self, lock_frame: Option<u64>) -> f32 {
+        let age = match lock_frame {
+            Some(lock_frame) => self.frame.saturating_sub(lock_frame),
+            None => return 1.0,
+        };
+        match self.block_visibility {
+            BlockVisibility::Visible => 1.0,
+            BlockVisibility::Fading { delay, duration } => {
+                if age < delay {
+                    1.0
+                } else if age >= delay + duration {
+                    0.0
+                } else {
+                    1.0 - (age - delay) as f32 / duration as f32
+                }
+            }
+            BlockVisibility::Invisible => 0.0,
+        }
+    }
+
     pub fn draw(&mut self) {
         let pf = self.game.playfield();
+        let clear_progress = if let Phase::LineClear | Phase::BoardClear = self.game.phase() {
+            self.game.phase_progress()
+        } else {
+            0.0
+        };
         for y in 0..pf.height() as i32 {
+            let clearing = self.game.clearing_lines().contains(&(y as u32));
             for x in 0..pf.width() as i32 {
                 let block = pf.get_block((x, y).into());
-                if let Block::Set(ref id) = block {
-                    self.draw
-                        .set_block(x as u32, y as u32, Self::block_color(*id));
+                let mut color = if let Block::Set(ref id) = block {
+                    let (r, g, b, a) = Self::block_color(*id);
+                    let lock_frame = self.game.block_lock_frame(x as u32, y as u32);
+                    (r, g, b, a * self.block_alpha(lock_frame))
                 } else {
-                    self.draw
-                        .set_block(x as u32, y as u32, Self::block_color(0));
+                    Self::block_color(0)
+                };
+                if clearing {
+                    // Lines being cleared are revealed as they flash
+                    color.3 = color.3.max(clear_progress);
+                    color = Self::clearing_color(color, clear_progress);
                 }
+                self.draw.set_block(x as u32, y as u32, color);
             }
         }
         if let Some((ref fig, pos)) = self.game.current_figure() {
@@ -138,6 +622,18 @@ impl GameContext {
                 );
             }
         }
+        self.draw.clear_outlines();
+        if let (true, Some((fig, _))) = (self.hint, self.game.current_figure()) {
+            if let Some(hint) = self.computer_player.hint(&self.game) {
+                for (x, y, _) in fig.face(hint.pos.dir()) {
+                    self.draw.add_outline(
+                        (i32::from(*x) + hint.pos.x()) as u32,
+                        (i32::from(*y) + hint.pos.y()) as u32,
+                        HINT_COLOR,
+                    );
+                }
+            }
+        }
         self.draw.draw_blocks();
     }
 }
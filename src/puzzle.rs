@@ -0,0 +1,221 @@
+use std::str::FromStr;
+
+use rstris::block::Block;
+use rstris::figure::Figure;
+use rstris::playfield::Playfield;
+
+use crate::fumen::*;
+use crate::game::*;
+use crate::game_mode::*;
+
+// What needs to be done to solve a puzzle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PuzzleGoal {
+    // Clear at least this many lines
+    ClearLines(u32),
+    // Clear lines until the playfield is empty
+    PerfectClear,
+}
+
+impl FromStr for PuzzleGoal {
+    type Err = String;
+
+    // Parse goals on the form "clear-lines:<count>" or "perfect-clear"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "perfect-clear" {
+            return Ok(PuzzleGoal::PerfectClear);
+        }
+        if let Some(count) = s.strip_prefix("clear-lines:") {
+            return count
+                .trim()
+                .parse()
+                .map(PuzzleGoal::ClearLines)
+                .map_err(|_| format!("Invalid line count in puzzle goal: {}", s));
+        }
+        Err(format!("Unknown puzzle goal: {}", s))
+    }
+}
+
+pub struct Puzzle {
+    pf: Playfield,
+    sequence: Vec<Figure>,
+    goal: PuzzleGoal,
+}
+
+impl Puzzle {
+    //
+    // Create a puzzle from a playfield and a list of figure ids to play.
+    // Figure id n refers to the n:th of the available figures (starting
+    // at 1).
+    //
+    pub fn new(
+        pf: Playfield,
+        figure_ids: &[u8],
+        available_figures: &[Figure],
+        goal: PuzzleGoal,
+    ) -> Result<Self, String> {
+        let mut sequence = Vec::new();
+        for id in figure_ids {
+            let index = (*id as usize).wrapping_sub(1);
+            match available_figures.get(index) {
+                Some(figure) => sequence.push(figure.clone()),
+                None => return Err(format!("Unknown figure id in puzzle: {}", id)),
+            }
+        }
+        Ok(Puzzle { pf, sequence, goal })
+    }
+
+    //
+    // Create a puzzle from a fumen. The playfield is taken from the first
+    // page and the figures to play from all pages.
+    //
+    pub fn from_fumen(
+        fumen: &str,
+        height: u32,
+        available_figures: &[Figure],
+        goal: PuzzleGoal,
+    ) -> Result<Self, String> {
+        let pages = decode_fumen(fumen, available_figures, height)?;
+        let pf = match pages.first() {
+            Some(page) => page.pf.clone(),
+            None => return Err("Fumen has no pages".to_string()),
+        };
+        let sequence = pages
+            .into_iter()
+            .filter_map(|page| page.figure.map(|(fig, _)| fig))
+            .collect();
+        Ok(Puzzle { pf, sequence, goal })
+    }
+
+    //
+    // Create a puzzle playfield from a list of block ids (0 for empty),
+    // given row by row from the top.
+    //
+    pub fn playfield_from_blocks(
+        width: u32,
+        height: u32,
+        blocks: &[u8],
+    ) -> Result<Playfield, String> {
+        let size = match width.checked_mul(height) {
+            Some(size) => size,
+            None => {
+                return Err(format!(
+                    "Puzzle playfield is too large: {}x{}",
+                    width, height
+                ))
+            }
+        };
+        if blocks.len() != size as usize {
+            return Err(format!(
+                "Puzzle has {} blocks but a {}x{} playfield needs {}",
+                blocks.len(),
+                width,
+                height,
+                size
+            ));
+        }
+        let mut pf = Playfield::new("Puzzle", width, height);
+        for (i, id) in blocks.iter().enumerate() {
+            if *id != 0 {
+                let x = (i % width as usize) as i32;
+                let y = (i / width as usize) as i32;
+                pf.set_block((x, y).into(), Block::Set(*id));
+            }
+        }
+        Ok(pf)
+    }
+
+    // Create a game starting in the puzzle position
+    pub fn start(&self, config: GameConfig) -> Game {
+        Game::new_with_sequence(self.pf.clone(), self.sequence.clone(), config)
+    }
+}
+
+impl GameMode for Puzzle {
+    fn status(&self, game: &Game) -> ModeStatus {
+        let solved = match self.goal {
+            PuzzleGoal::ClearLines(count) => game.lines_cleared() >= count,
+            PuzzleGoal::PerfectClear => game.perfect_clears() > 0,
+        };
+        if solved {
+            ModeStatus::Completed
+        } else if game.is_game_over() {
+            ModeStatus::Failed
+        } else {
+            ModeStatus::InProgress
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_playfield::*;
+    use crate::test_helpers::*;
+
+    fn play(puzzle: &Puzzle) -> Game {
+        let config = GameConfig {
+            gravity: 20.0,
+            lock_delay: 0,
+            entry_delay: 0,
+            ..GameConfig::default()
+        };
+        let mut game = puzzle.start(config);
+        let mut frame = 0;
+        while puzzle.status(&game) == ModeStatus::InProgress && frame < 1000 {
+            game.update(frame);
+            frame += 1;
+        }
+        game
+    }
+
+    #[test]
+    fn goal_from_str() {
+        assert_eq!("perfect-clear".parse(), Ok(PuzzleGoal::PerfectClear));
+        assert_eq!(" clear-lines: 3 ".parse(), Ok(PuzzleGoal::ClearLines(3)));
+        assert!("clear-lines:".parse::<PuzzleGoal>().is_err());
+        assert!("clear-lines:-1".parse::<PuzzleGoal>().is_err());
+        assert!("clear-all".parse::<PuzzleGoal>().is_err());
+    }
+
+    #[test]
+    fn playfield_from_blocks() {
+        let pf = Puzzle::playfield_from_blocks(2, 2, &[0, 1, 2, 0]).unwrap();
+        assert_eq!(playfield_to_ascii(&pf), ".1\n2.\n");
+        assert!(Puzzle::playfield_from_blocks(2, 2, &[0, 1, 2]).is_err());
+        assert!(Puzzle::playfield_from_blocks(u32::MAX, 2, &[]).is_err());
+    }
+
+    #[test]
+    fn status() {
+        let figures = crate::init_figures();
+        let pf = playfield_from_ascii("....\n....\n1...\n1..1\n").unwrap();
+
+        // One line cleared by the only figure
+        let goal = PuzzleGoal::ClearLines(1);
+        let puzzle = Puzzle::new(pf.clone(), &[O as u8], &figures, goal).unwrap();
+        assert_eq!(
+            puzzle.status(&puzzle.start(GameConfig::default())),
+            ModeStatus::InProgress
+        );
+        let game = play(&puzzle);
+        assert_eq!(game.lines_cleared(), 1);
+        assert_eq!(puzzle.status(&game), ModeStatus::Completed);
+
+        // Running out of figures before the goal is reached
+        for goal in &[PuzzleGoal::ClearLines(2), PuzzleGoal::PerfectClear] {
+            let puzzle = Puzzle::new(pf.clone(), &[O as u8], &figures, *goal).unwrap();
+            let game = play(&puzzle);
+            assert!(game.is_game_over());
+            assert_eq!(puzzle.status(&game), ModeStatus::Failed);
+        }
+
+        // Perfect clear
+        let pf = playfield_from_ascii("....\n....\n1..1\n1..1\n").unwrap();
+        let puzzle = Puzzle::new(pf, &[O as u8], &figures, PuzzleGoal::PerfectClear).unwrap();
+        assert_eq!(puzzle.status(&play(&puzzle)), ModeStatus::Completed);
+
+        assert!(Puzzle::new(Playfield::new("Puzzle", 4, 4), &[0], &figures, goal).is_err());
+    }
+}
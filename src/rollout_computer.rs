@@ -0,0 +1,270 @@
+use rstris::figure::Figure;
+use rstris::find_placement::*;
+use rstris::playfield::Playfield;
+use rstris::position::Position;
+
+use rand::rngs::SmallRng;
+use rand::{RngExt, SeedableRng};
+
+use crate::computer_player::*;
+use crate::weighted_computer::*;
+
+//
+// Rates placings by Monte Carlo rollouts. After each placing a number of
+// random figures are played by a fast default policy, and the placing is
+// rated by the average number of figures placed before topping out plus
+// the lines cleared. All placings of a figure are rated with the same
+// random figures. Short rollouts often end up even, so the rating of the
+// policy for the placing itself is added, scaled below a line, to break
+// the ties.
+//
+// The random figures are drawn from the seed and the playfield, so rating
+// the same playfield again, e.g. for a hint, plays the same figures and
+// doesn't change the ratings to come.
+//
+// Only the playfield is simulated, so rollouts run the same natively as
+// in the browser.
+//
+pub struct RolloutComputer {
+    figures: Vec<Figure>,
+    rollouts: usize,
+    depth: usize,
+    seed: u64,
+    // Picks the placing of each figure in a rollout
+    policy: WeightedComputer,
+    // Figures (by index) played by each rollout
+    sequences: Vec<Vec<usize>>,
+
+    // Some cache variables
+    pf: Option<Playfield>,
+    placings: Vec<Position>,
+}
+
+// Scale of the policy rating breaking ties between placings
+const TIE_BREAK_SCALE: f32 = 0.02;
+
+// Average result of the rollouts after a placing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RolloutResult {
+    // Figures placed before topping out, at most the depth
+    pub survival: f32,
+    // Lines cleared by the placing and the figures after it
+    pub lines: f32,
+    // Rating of the placing by the policy, scaled to break ties
+    pub tie_break: f32,
+}
+
+impl RolloutComputer {
+    pub fn new(figures: Vec<Figure>, rollouts: usize, depth: usize, seed: u64) -> Self {
+        RolloutComputer {
+            figures,
+            rollouts: rollouts.max(1),
+            depth,
+            seed,
+            policy: WeightedComputer::el_tetris(),
+            sequences: Vec::new(),
+            pf: None,
+            placings: Vec::new(),
+        }
+    }
+
+    pub fn rollouts(&self) -> usize {
+        self.rollouts
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    // Draw the figures for the rollouts on the playfield
+    fn new_sequences(&mut self, pf: &Playfield) {
+        let (figure_count, depth) = (self.figures.len(), self.depth);
+        let rng = &mut SmallRng::seed_from_u64(self.seed ^ playfield_hash(pf));
+        self.sequences = (0..self.rollouts)
+            .map(|_| {
+                (0..depth)
+                    .map(|_| rng.random_range(0..figure_count))
+                    .collect()
+            })
+            .collect();
+    }
+
+    // Run the rollouts after a placing
+    pub fn rollout(
+        &mut self,
+        current_pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+    ) -> RolloutResult {
+        if self.sequences.is_empty() {
+            self.new_sequences(current_pf);
+        }
+        let pf = self.pf.get_or_insert_with(|| current_pf.clone());
+        let mut survival = 0;
+        let mut lines = 0;
+        for sequence in &self.sequences {
+            pf.copy(current_pf);
+            lines += place_figure(pf, fig, pos);
+            for index in sequence {
+                let next_fig = &self.figures[*index];
+                match best_drop_placing(&mut self.policy, &mut self.placings, pf, next_fig) {
+                    Some(next_pos) => lines += place_figure(pf, next_fig, next_pos),
+                    // Topped out
+                    None => break,
+                }
+                survival += 1;
+            }
+        }
+        RolloutResult {
+            survival: survival as f32 / self.rollouts as f32,
+            lines: lines as f32 / self.rollouts as f32,
+            tie_break: self.policy.eval_placing(current_pf, fig, pos) * TIE_BREAK_SCALE,
+        }
+    }
+}
+
+// Hash of the blocks set on the playfield (FNV-1a)
+fn playfield_hash(pf: &Playfield) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for row in pf.blocks().row_iter() {
+        for block in row.iter() {
+            hash ^= u64::from(block.is_set());
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+// Best placing by the policy among those reachable by dropping the figure
+// straight down from the top of the playfield
+fn best_drop_placing(
+    policy: &mut WeightedComputer,
+    placings: &mut Vec<Position>,
+    pf: &Playfield,
+    fig: &Figure,
+) -> Option<Position> {
+    placings.clear();
+    find_placement(placings, pf, fig);
+    let mut best: Option<(Position, f32)> = None;
+    for pos in placings.iter() {
+        let dropped =
+            (0..pos.y()).all(|y| !fig.test_collision(pf, Position::new((pos.x(), y, pos.dir()))));
+        if !dropped || fig.test_collision(pf, *pos) {
+            continue;
+        }
+        let eval = policy.eval_placing(pf, fig, *pos);
+        if best.is_none_or(|(_, best_eval)| eval > best_eval) {
+            best = Some((*pos, eval));
+        }
+    }
+    best.map(|(pos, _)| pos)
+}
+
+impl ComputerType for RolloutComputer {
+    fn init_eval(&mut self, pf: &Playfield, _avail_placings: usize) {
+        self.new_sequences(pf);
+    }
+
+    fn eval_placing(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> f32 {
+        let result = self.rollout(pf, fig, pos);
+        result.survival + result.lines + result.tie_break
+    }
+
+    fn explain_placing(
+        &mut self,
+        pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+    ) -> Vec<(&'static str, f32)> {
+        let result = self.rollout(pf, fig, pos);
+        vec![
+            ("survival", result.survival),
+            ("lines", result.lines),
+            ("tie_break", result.tie_break),
+        ]
+    }
+
+    fn looks_ahead(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_playfield::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn rollouts() {
+        let o = figure(O);
+        let mut computer = RolloutComputer::new(vec![figure(I)], 4, 3, 1);
+        let pf = playfield_from_ascii(
+            "......\n......\n......\n......\n......\n......\n11.111\n11.111\n",
+        )
+        .unwrap();
+        computer.init_eval(&pf, 0);
+
+        // Leaving the gap open lets the I figures clear lines and survive
+        let open = computer.rollout(&pf, &o, Position::new((0, 4, 0)));
+        assert_eq!(open.survival, 3.0);
+        assert!(open.lines >= 2.0);
+
+        // Covering it up gives no lines and tops out sooner
+        let covered = computer.rollout(&pf, &o, Position::new((2, 4, 0)));
+        assert!(covered.lines < open.lines);
+        assert!(
+            computer.eval_placing(&pf, &o, Position::new((2, 4, 0)))
+                < computer.eval_placing(&pf, &o, Position::new((0, 4, 0)))
+        );
+    }
+
+    #[test]
+    fn seeded_rollouts() {
+        let figures = crate::init_figures();
+        let pf = Playfield::new("Test", 10, 8);
+        let o = figure(O);
+        let rate = |seed| {
+            let mut computer = RolloutComputer::new(figures.clone(), 8, 6, seed);
+            computer.init_eval(&pf, 0);
+            computer.rollout(&pf, &o, Position::new((0, 6, 0)))
+        };
+        assert_eq!(rate(1), rate(1));
+    }
+
+    #[test]
+    fn same_sequences_for_same_playfield() {
+        let pf = Playfield::new("Test", 10, 8);
+        let mut other_pf = pf.clone();
+        let o = figure(O);
+        o.place(&mut other_pf, Position::new((0, 6, 0)));
+        let mut computer = RolloutComputer::new(crate::init_figures(), 8, 6, 1);
+        computer.init_eval(&pf, 0);
+        let sequences = computer.sequences.clone();
+        let result = computer.rollout(&pf, &o, Position::new((4, 6, 0)));
+
+        // Rating something else in between changes nothing
+        computer.init_eval(&other_pf, 0);
+        assert_ne!(computer.sequences, sequences);
+        computer.init_eval(&pf, 0);
+        assert_eq!(computer.sequences, sequences);
+        assert_eq!(computer.rollout(&pf, &o, Position::new((4, 6, 0))), result);
+    }
+
+    #[test]
+    fn explain() {
+        let figures = crate::init_figures();
+        let mut game = sequence_game(&"..........\n".repeat(8), figures.clone(), quick_config());
+        game.update(0);
+        let computer = RolloutComputer::new(figures, 4, 3, 1);
+        let mut player = ComputerPlayer::new(2.0, computer);
+
+        // Only the terms of the rollouts, nothing added by a search
+        let explanations = player.explain(&game);
+        assert!(!explanations.is_empty());
+        for explanation in &explanations {
+            let names: Vec<&str> = explanation.terms.iter().map(|term| term.0).collect();
+            assert_eq!(names, ["survival", "lines", "tie_break"]);
+        }
+    }
+}
@@ -0,0 +1,620 @@
+use rstris::figure::Figure;
+use rstris::movement::Movement;
+use rstris::playfield::Playfield;
+use rstris::position::Position;
+use wasm_bindgen::prelude::*;
+
+// The standard figures, known by the block ids they are built from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Piece {
+    T,
+    J,
+    L,
+    O,
+    S,
+    Z,
+    I,
+}
+
+impl Piece {
+    pub fn of(fig: &Figure) -> Option<Piece> {
+        let id = fig.face(0).iter().map(|(_, _, id)| *id).next()?;
+        match id {
+            1 => Some(Piece::T),
+            2 => Some(Piece::J),
+            3 => Some(Piece::L),
+            4 => Some(Piece::O),
+            5 => Some(Piece::S),
+            6 => Some(Piece::Z),
+            7 => Some(Piece::I),
+            _ => None,
+        }
+    }
+}
+
+// Which rotation system a game uses
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+    // Figures turn within their box and never kick
+    Basic,
+    // Super Rotation System (guideline)
+    Srs,
+    // Arika Rotation System (TGM)
+    Ars,
+    // NES classic, no kicks
+    Nes,
+}
+
+impl Rotation {
+    pub fn system(self) -> Box<dyn RotationSystem> {
+        match self {
+            Rotation::Basic => Box::new(BasicRotation),
+            Rotation::Srs => Box::new(Srs),
+            Rotation::Ars => Box::new(Ars),
+            Rotation::Nes => Box::new(NesRotation),
+        }
+    }
+}
+
+//
+// A rotation system decides how figures enter the playfield, the point
+// they rotate around and where they may be kicked when a rotation doesn't
+// fit.
+//
+pub trait RotationSystem {
+    // Direction a figure enters the playfield in
+    fn spawn_dir(&self, fig: &Figure) -> i32;
+
+    // Position of a figure turned a quarter, before testing any kicks
+    fn rotate(&self, fig: &Figure, pos: Position, clockwise: bool) -> Position;
+
+    // Offsets to test, in order, for a figure rotated from one position
+    // to another. The first one that fits is used.
+    fn kicks(&self, pf: &Playfield, fig: &Figure, from: Position, to: Position) -> Vec<(i32, i32)>;
+}
+
+// Rotate a figure on the playfield, kicking it if needed. Kicks are
+// multiplied by scale for big figures. Returns None if the figure can't
+// be rotated.
+pub fn rotate_figure(
+    rotation_system: &dyn RotationSystem,
+    pf: &Playfield,
+    fig: &Figure,
+    pos: Position,
+    movement: Movement,
+    scale: i32,
+) -> Option<Position> {
+    let clockwise = match movement {
+        Movement::RotateCW => true,
+        Movement::RotateCCW => false,
+        _ => return None,
+    };
+    let rotated = rotation_system.rotate(fig, pos, clockwise);
+    rotation_system
+        .kicks(pf, fig, pos, rotated)
+        .into_iter()
+        .map(|(dx, dy)| {
+            Position::new((
+                rotated.x() + dx * scale,
+                rotated.y() + dy * scale,
+                rotated.dir(),
+            ))
+        })
+        .find(|kicked| !fig.test_collision(pf, *kicked))
+}
+
+pub struct BasicRotation;
+
+impl RotationSystem for BasicRotation {
+    fn spawn_dir(&self, _fig: &Figure) -> i32 {
+        0
+    }
+
+    fn rotate(&self, _fig: &Figure, pos: Position, clockwise: bool) -> Position {
+        let movement = if clockwise {
+            Movement::RotateCW
+        } else {
+            Movement::RotateCCW
+        };
+        Position::apply_move(&pos, movement)
+    }
+
+    fn kicks(
+        &self,
+        _pf: &Playfield,
+        _fig: &Figure,
+        _from: Position,
+        _to: Position,
+    ) -> Vec<(i32, i32)> {
+        vec![(0, 0)]
+    }
+}
+
+type Blocks = [(i32, i32); 4];
+
+//
+// Rotation systems given as the blocks of each piece in each of its four
+// states (0 being the spawn state, then turning clockwise), relative to a
+// point that stays put as the piece rotates. The states are matched
+// against the faces of the figures to find how they turn.
+//
+trait StateTable {
+    fn state_blocks(&self, piece: Piece, state: i32) -> Blocks;
+}
+
+fn face_blocks(fig: &Figure, dir: i32) -> Vec<(i32, i32)> {
+    fig.face(dir)
+        .iter()
+        .map(|(x, y, _)| (i32::from(*x), i32::from(*y)))
+        .collect()
+}
+
+fn min_corner(blocks: &[(i32, i32)]) -> (i32, i32) {
+    let x = blocks.iter().map(|b| b.0).min().unwrap_or(0);
+    let y = blocks.iter().map(|b| b.1).min().unwrap_or(0);
+    (x, y)
+}
+
+// Blocks moved to the top left corner and sorted, to compare shapes
+fn shape(blocks: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let (min_x, min_y) = min_corner(blocks);
+    let mut shape: Vec<(i32, i32)> = blocks.iter().map(|(x, y)| (x - min_x, y - min_y)).collect();
+    shape.sort();
+    shape
+}
+
+// How the states of a piece map to figure directions, as the direction of
+// state 0 and the direction step for each clockwise turn
+fn state_dirs(table: &dyn StateTable, fig: &Figure, piece: Piece) -> Option<(i32, i32)> {
+    for step in &[1, 3] {
+        for first_dir in 0..4 {
+            if (0..4).all(|state| {
+                let dir = (first_dir + step * state) % 4;
+                shape(&face_blocks(fig, dir)) == shape(&table.state_blocks(piece, state))
+            }) {
+                return Some((first_dir, *step));
+            }
+        }
+    }
+    None
+}
+
+fn table_spawn_dir(table: &dyn StateTable, fig: &Figure) -> i32 {
+    Piece::of(fig)
+        .and_then(|piece| state_dirs(table, fig, piece))
+        .map_or(0, |(first_dir, _)| first_dir)
+}
+
+// State of a figure in the given direction
+fn table_state(first_dir: i32, step: i32, dir: i32) -> i32 {
+    // A step of 3 is its own inverse (3 * 3 = 1 modulo 4)
+    ((dir - first_dir).rem_euclid(4) * step) % 4
+}
+
+// Where the figure face is drawn from relative to the fixed point of the
+// state table
+fn table_offset(
+    table: &dyn StateTable,
+    fig: &Figure,
+    piece: Piece,
+    state: i32,
+    dir: i32,
+) -> (i32, i32) {
+    let (state_x, state_y) = min_corner(&table.state_blocks(piece, state));
+    let (face_x, face_y) = min_corner(&face_blocks(fig, dir));
+    (state_x - face_x, state_y - face_y)
+}
+
+fn table_rotate(table: &dyn StateTable, fig: &Figure, pos: Position, clockwise: bool) -> Position {
+    let piece_dirs =
+        Piece::of(fig).and_then(|piece| state_dirs(table, fig, piece).map(|dirs| (piece, dirs)));
+    let (piece, (first_dir, step)) = match piece_dirs {
+        Some(piece_dirs) => piece_dirs,
+        None => return BasicRotation.rotate(fig, pos, clockwise),
+    };
+    let from_state = table_state(first_dir, step, pos.dir());
+    let to_state = (from_state + if clockwise { 1 } else { 3 }) % 4;
+    let to_dir = (first_dir + step * to_state) % 4;
+    let from_offset = table_offset(table, fig, piece, from_state, pos.dir());
+    let to_offset = table_offset(table, fig, piece, to_state, to_dir);
+    Position::new((
+        pos.x() - from_offset.0 + to_offset.0,
+        pos.y() - from_offset.1 + to_offset.1,
+        to_dir,
+    ))
+}
+
+// Turn blocks clockwise around a center given in half blocks
+fn turn_blocks(blocks: Blocks, center2: (i32, i32), turns: i32) -> Blocks {
+    let mut blocks = blocks;
+    for _ in 0..turns {
+        for block in blocks.iter_mut() {
+            *block = (
+                (center2.0 + center2.1) / 2 - block.1,
+                (center2.1 - center2.0) / 2 + block.0,
+            );
+        }
+    }
+    blocks
+}
+
+// Super Rotation System, as in the guideline games
+pub struct Srs;
+
+impl StateTable for Srs {
+    fn state_blocks(&self, piece: Piece, state: i32) -> Blocks {
+        // Spawn states and rotation centers within their boxes
+        let (blocks, center2) = match piece {
+            Piece::T => ([(1, 0), (0, 1), (1, 1), (2, 1)], (2, 2)),
+            Piece::J => ([(0, 0), (0, 1), (1, 1), (2, 1)], (2, 2)),
+            Piece::L => ([(2, 0), (0, 1), (1, 1), (2, 1)], (2, 2)),
+            Piece::S => ([(1, 0), (2, 0), (0, 1), (1, 1)], (2, 2)),
+            Piece::Z => ([(0, 0), (1, 0), (1, 1), (2, 1)], (2, 2)),
+            Piece::I => ([(0, 1), (1, 1), (2, 1), (3, 1)], (3, 3)),
+            Piece::O => ([(0, 0), (1, 0), (0, 1), (1, 1)], (1, 1)),
+        };
+        turn_blocks(blocks, center2, state)
+    }
+}
+
+// Kicks from each state when turning clockwise, as given by the guideline
+// (with y pointing up). Turning counter clockwise from a state uses the
+// negated kicks of turning clockwise into it.
+const SRS_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+const SRS_I_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+
+impl RotationSystem for Srs {
+    fn spawn_dir(&self, fig: &Figure) -> i32 {
+        table_spawn_dir(self, fig)
+    }
+
+    fn rotate(&self, fig: &Figure, pos: Position, clockwise: bool) -> Position {
+        table_rotate(self, fig, pos, clockwise)
+    }
+
+    fn kicks(
+        &self,
+        _pf: &Playfield,
+        fig: &Figure,
+        from: Position,
+        to: Position,
+    ) -> Vec<(i32, i32)> {
+        let piece = match Piece::of(fig) {
+            Some(Piece::O) | None => return vec![(0, 0)],
+            Some(piece) => piece,
+        };
+        let (first_dir, step) = match state_dirs(self, fig, piece) {
+            Some(dirs) => dirs,
+            None => return vec![(0, 0)],
+        };
+        let from_state = table_state(first_dir, step, from.dir());
+        let to_state = table_state(first_dir, step, to.dir());
+        let table = if piece == Piece::I {
+            &SRS_I_KICKS
+        } else {
+            &SRS_KICKS
+        };
+        let clockwise = to_state == (from_state + 1) % 4;
+        table[if clockwise { from_state } else { to_state } as usize]
+            .iter()
+            .map(|(x, y)| if clockwise { (*x, -y) } else { (-x, *y) })
+            .collect()
+    }
+}
+
+// Arika Rotation System, as in the TGM games
+pub struct Ars;
+
+impl StateTable for Ars {
+    fn state_blocks(&self, piece: Piece, state: i32) -> Blocks {
+        // Pieces stay at the bottom of their boxes when turning
+        let states: [Blocks; 4] = match piece {
+            Piece::T => [
+                [(0, 1), (1, 1), (2, 1), (1, 2)],
+                [(1, 0), (0, 1), (1, 1), (1, 2)],
+                [(1, 1), (0, 2), (1, 2), (2, 2)],
+                [(1, 0), (1, 1), (2, 1), (1, 2)],
+            ],
+            Piece::J => [
+                [(0, 1), (1, 1), (2, 1), (2, 2)],
+                [(1, 0), (1, 1), (0, 2), (1, 2)],
+                [(0, 1), (0, 2), (1, 2), (2, 2)],
+                [(1, 0), (2, 0), (1, 1), (1, 2)],
+            ],
+            Piece::L => [
+                [(0, 1), (1, 1), (2, 1), (0, 2)],
+                [(0, 0), (1, 0), (1, 1), (1, 2)],
+                [(2, 1), (0, 2), (1, 2), (2, 2)],
+                [(1, 0), (1, 1), (1, 2), (2, 2)],
+            ],
+            Piece::S => {
+                let flat = [(1, 1), (2, 1), (0, 2), (1, 2)];
+                let upright = [(0, 0), (0, 1), (1, 1), (1, 2)];
+                [flat, upright, flat, upright]
+            }
+            Piece::Z => {
+                let flat = [(0, 1), (1, 1), (1, 2), (2, 2)];
+                let upright = [(2, 0), (1, 1), (2, 1), (1, 2)];
+                [flat, upright, flat, upright]
+            }
+            Piece::I => {
+                let flat = [(0, 1), (1, 1), (2, 1), (3, 1)];
+                let upright = [(2, 0), (2, 1), (2, 2), (2, 3)];
+                [flat, upright, flat, upright]
+            }
+            Piece::O => [[(1, 1), (2, 1), (1, 2), (2, 2)]; 4],
+        };
+        states[state as usize]
+    }
+}
+
+impl RotationSystem for Ars {
+    fn spawn_dir(&self, fig: &Figure) -> i32 {
+        table_spawn_dir(self, fig)
+    }
+
+    fn rotate(&self, fig: &Figure, pos: Position, clockwise: bool) -> Position {
+        table_rotate(self, fig, pos, clockwise)
+    }
+
+    //
+    // Try one step right and then one step left. The I never kicks, and
+    // neither do the T, J and L if the first block in the way (reading
+    // the box row by row) is in the center column.
+    //
+    fn kicks(
+        &self,
+        pf: &Playfield,
+        fig: &Figure,
+        _from: Position,
+        to: Position,
+    ) -> Vec<(i32, i32)> {
+        let piece = match Piece::of(fig) {
+            Some(Piece::I) | Some(Piece::O) | None => return vec![(0, 0)],
+            Some(piece) => piece,
+        };
+        if matches!(piece, Piece::T | Piece::J | Piece::L) {
+            if let Some((first_dir, step)) = state_dirs(self, fig, piece) {
+                let state = table_state(first_dir, step, to.dir());
+                let mut blocks = self.state_blocks(piece, state);
+                blocks.sort_by_key(|(x, y)| (*y, *x));
+                let (offset_x, offset_y) = table_offset(self, fig, piece, state, to.dir());
+                let first_in_way = blocks.iter().find(|(x, y)| {
+                    let pf_pos = (to.x() + x - offset_x, to.y() + y - offset_y);
+                    pf_pos.0 < 0
+                        || pf_pos.1 < 0
+                        || pf_pos.0 >= pf.width() as i32
+                        || pf_pos.1 >= pf.height() as i32
+                        || pf.get_block(pf_pos.into()).is_set()
+                });
+                if let Some((1, _)) = first_in_way {
+                    return vec![(0, 0)];
+                }
+            }
+        }
+        vec![(0, 0), (1, 0), (-1, 0)]
+    }
+}
+
+// Rotation as in NES Tetris. The I, S and Z only have two states and
+// nothing ever kicks
+pub struct NesRotation;
+
+impl StateTable for NesRotation {
+    fn state_blocks(&self, piece: Piece, state: i32) -> Blocks {
+        // Pieces turn around their center block at (0, 0)
+        let (flat, upright) = match piece {
+            Piece::T => return turn_blocks([(-1, 0), (0, 0), (1, 0), (0, 1)], (0, 0), state),
+            Piece::J => return turn_blocks([(-1, 0), (0, 0), (1, 0), (1, 1)], (0, 0), state),
+            Piece::L => return turn_blocks([(-1, 0), (0, 0), (1, 0), (-1, 1)], (0, 0), state),
+            Piece::O => return [(-1, 0), (0, 0), (-1, 1), (0, 1)],
+            Piece::S => (
+                [(0, 0), (1, 0), (-1, 1), (0, 1)],
+                [(0, -1), (0, 0), (1, 0), (1, 1)],
+            ),
+            Piece::Z => (
+                [(-1, 0), (0, 0), (0, 1), (1, 1)],
+                [(1, -1), (0, 0), (1, 0), (0, 1)],
+            ),
+            Piece::I => (
+                [(-2, 0), (-1, 0), (0, 0), (1, 0)],
+                [(0, -2), (0, -1), (0, 0), (0, 1)],
+            ),
+        };
+        if state % 2 == 0 {
+            flat
+        } else {
+            upright
+        }
+    }
+}
+
+impl RotationSystem for NesRotation {
+    fn spawn_dir(&self, fig: &Figure) -> i32 {
+        table_spawn_dir(self, fig)
+    }
+
+    fn rotate(&self, fig: &Figure, pos: Position, clockwise: bool) -> Position {
+        table_rotate(self, fig, pos, clockwise)
+    }
+
+    fn kicks(
+        &self,
+        _pf: &Playfield,
+        _fig: &Figure,
+        _from: Position,
+        _to: Position,
+    ) -> Vec<(i32, i32)> {
+        vec![(0, 0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_playfield::*;
+    use crate::test_helpers::*;
+
+    fn figure_blocks(fig: &Figure, pos: Position) -> Vec<(i32, i32)> {
+        let mut blocks: Vec<(i32, i32)> = face_blocks(fig, pos.dir())
+            .iter()
+            .map(|(x, y)| (x + pos.x(), y + pos.y()))
+            .collect();
+        blocks.sort();
+        blocks
+    }
+
+    //
+    // Rotate a figure drawn as 'x' on a playfield and draw where it ends
+    // up. A figure that can't be rotated stays where it is.
+    //
+    fn rotate_in(
+        rotation_system: &dyn RotationSystem,
+        figure_id: usize,
+        before: &str,
+        movement: Movement,
+    ) -> String {
+        let fig = figure(figure_id);
+        let pf = playfield_from_ascii(&before.replace('x', ".")).unwrap();
+        let mut marked = Vec::new();
+        for (y, row) in before.lines().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == 'x' {
+                    marked.push((x as i32, y as i32));
+                }
+            }
+        }
+        marked.sort();
+
+        // Find the figure, preferring the spawn state
+        let spawn_dir = rotation_system.spawn_dir(&fig);
+        let (width, height) = (pf.width() as i32, pf.height() as i32);
+        let pos = (0..4)
+            .flat_map(|turn| {
+                (-4..width).flat_map(move |x| {
+                    (-4..height).map(move |y| Position::new((x, y, (spawn_dir + turn) % 4)))
+                })
+            })
+            .find(|pos| figure_blocks(&fig, *pos) == marked)
+            .expect("Figure not found");
+
+        let pos = rotate_figure(rotation_system, &pf, &fig, pos, movement, 1).unwrap_or(pos);
+        let mut rows: Vec<Vec<char>> = playfield_to_ascii(&pf)
+            .lines()
+            .map(|row| row.chars().collect())
+            .collect();
+        for (x, y) in figure_blocks(&fig, pos) {
+            rows[y as usize][x as usize] = 'x';
+        }
+        let mut after = String::new();
+        for row in rows {
+            after.extend(row);
+            after.push('\n');
+        }
+        after
+    }
+
+    #[test]
+    fn srs_spawn_and_center() {
+        let t = figure(T);
+        let pf = Playfield::new("Playfield", 5, 4);
+        let pos = Position::new((1, 0, Srs.spawn_dir(&t)));
+        assert!(!t.test_collision(&pf, pos));
+        let mut spawned = figure_blocks(&t, pos);
+        spawned
+            .iter_mut()
+            .for_each(|b| *b = (b.0 - pos.x(), b.1 - pos.y()));
+        assert_eq!(shape(&spawned), vec![(0, 1), (1, 0), (1, 1), (2, 1)]);
+
+        let before = ".....\n..x..\n.xxx.\n.....\n";
+        let after = ".....\n..x..\n..xx.\n..x..\n";
+        assert_eq!(rotate_in(&Srs, T, before, Movement::RotateCW), after);
+        assert_eq!(rotate_in(&Srs, T, after, Movement::RotateCCW), before);
+    }
+
+    #[test]
+    fn srs_wall_kicks() {
+        // Pointing right against the left wall, kicked right
+        let before = "x...\nxx..\nx...\n....\n";
+        let after = "....\nxxx.\n.x..\n....\n";
+        assert_eq!(rotate_in(&Srs, T, before, Movement::RotateCW), after);
+
+        // Pointing left against the right wall, kicked left
+        let before = "...x\n..xx\n...x\n....\n";
+        let after = "....\n.xxx\n..x.\n....\n";
+        assert_eq!(rotate_in(&Srs, T, before, Movement::RotateCCW), after);
+    }
+
+    #[test]
+    fn srs_i_floor_kick() {
+        // Flat I on the floor is kicked one step right and two up
+        let before = "......\n......\n......\n......\nxxxx..\n";
+        let after = "......\n...x..\n...x..\n...x..\n...x..\n";
+        assert_eq!(rotate_in(&Srs, I, before, Movement::RotateCW), after);
+    }
+
+    #[test]
+    fn ars_bottom_aligned() {
+        let before = ".....\n.xxx.\n..x..\n";
+        let after = "..x..\n.xx..\n..x..\n";
+        assert_eq!(rotate_in(&Ars, T, before, Movement::RotateCW), after);
+
+        // Pointing up it is still at the bottom of its box
+        let before = after;
+        let after = ".....\n..x..\n.xxx.\n";
+        assert_eq!(rotate_in(&Ars, T, before, Movement::RotateCW), after);
+    }
+
+    #[test]
+    fn ars_kicks() {
+        // Kicked left off the right wall, after trying right
+        let before = "...x\n..xx\n...x\n";
+        let after = "....\n..x.\n.xxx\n";
+        assert_eq!(rotate_in(&Ars, T, before, Movement::RotateCW), after);
+
+        // No kick when the center column is in the way
+        let before = "..1..\n.xxx.\n..x..\n";
+        assert_eq!(rotate_in(&Ars, T, before, Movement::RotateCW), before);
+
+        // I never kicks
+        let before = "x...\nx...\nx...\nx...\n";
+        assert_eq!(rotate_in(&Ars, I, before, Movement::RotateCW), before);
+    }
+
+    #[test]
+    fn nes_rotation() {
+        // S and Z only have two states
+        let before = "....\n..xx\n.xx.\n....\n";
+        let after = "..x.\n..xx\n...x\n....\n";
+        assert_eq!(
+            rotate_in(&NesRotation, S, before, Movement::RotateCW),
+            after
+        );
+        assert_eq!(
+            rotate_in(&NesRotation, S, after, Movement::RotateCW),
+            before
+        );
+        assert_eq!(
+            rotate_in(&NesRotation, S, after, Movement::RotateCCW),
+            before
+        );
+
+        // Nothing kicks
+        let before = "x...\nxx..\nx...\n....\n";
+        assert_eq!(
+            rotate_in(&NesRotation, T, before, Movement::RotateCW),
+            before
+        );
+    }
+}
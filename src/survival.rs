@@ -0,0 +1,43 @@
+use rstris::figure::Figure;
+use rstris::playfield::Playfield;
+
+use crate::game::*;
+use crate::game_mode::*;
+
+//
+// Survive as long as possible while garbage rows rise from the bottom,
+// faster and faster.
+//
+pub struct Survival {
+    rising_floor: RisingFloor,
+}
+
+impl Survival {
+    pub fn new(rising_floor: RisingFloor) -> Self {
+        Survival { rising_floor }
+    }
+
+    pub fn start(&self, pf: Playfield, available_figures: Vec<Figure>, config: GameConfig) -> Game {
+        let config = GameConfig {
+            rising_floor: Some(self.rising_floor.clone()),
+            ..config
+        };
+        Game::new(pf, available_figures, config)
+    }
+}
+
+impl GameMode for Survival {
+    // A survival game always ends by topping out, the score is the time
+    // survived until then
+    fn status(&self, game: &Game) -> ModeStatus {
+        if game.is_game_over() {
+            ModeStatus::Completed
+        } else {
+            ModeStatus::InProgress
+        }
+    }
+
+    fn allows_undo(&self) -> bool {
+        false
+    }
+}
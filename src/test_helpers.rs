@@ -0,0 +1,31 @@
+//
+// Fixtures shared by the tests of several modules
+//
+use rstris::figure::Figure;
+
+use crate::ascii_playfield::*;
+use crate::game::*;
+
+// Ids of the standard figures, see init_figures
+pub const T: usize = 1;
+pub const O: usize = 4;
+pub const S: usize = 5;
+pub const I: usize = 7;
+
+// Standard figure by id
+pub fn figure(id: usize) -> Figure {
+    crate::init_figures()[id - 1].clone()
+}
+
+// Figures spawn as soon as the game is updated, the rest as by default
+pub fn quick_config() -> GameConfig {
+    GameConfig {
+        entry_delay: 0,
+        ..GameConfig::default()
+    }
+}
+
+// Game on a playfield drawn in ASCII, playing the figures in order
+pub fn sequence_game(pf: &str, sequence: Vec<Figure>, config: GameConfig) -> Game {
+    Game::new_with_sequence(playfield_from_ascii(pf).unwrap(), sequence, config)
+}
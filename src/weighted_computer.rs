@@ -0,0 +1,330 @@
+use rstris::figure::Figure;
+use rstris::playfield::Playfield;
+use rstris::position::Position;
+
+use crate::computer_player::*;
+use crate::jitter_computer::*;
+
+//
+// Features of a playfield after placing a figure, as used by Pierre
+// Dellacherie's player and the players derived from it. Heights are
+// counted from the bottom of the playfield.
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Features {
+    // Height of the middle of the placed figure
+    pub landing_height: f32,
+    // Lines cleared times the number of blocks of the figure in them
+    pub eroded_cells: f32,
+    // Changes between set and empty blocks along the rows and columns,
+    // with the walls and floor counting as set
+    pub row_transitions: f32,
+    pub column_transitions: f32,
+    // Empty blocks with a set block somewhere above
+    pub holes: f32,
+    // Sum over all wells of 1 + 2 + .. + depth
+    pub wells: f32,
+    // Sum of height differences between neighbouring columns
+    pub bumpiness: f32,
+    // Sum of all column heights
+    pub aggregate_height: f32,
+}
+
+pub const FEATURE_COUNT: usize = 8;
+
+impl Features {
+    pub const NAMES: [&'static str; FEATURE_COUNT] = [
+        "landing_height",
+        "eroded_cells",
+        "row_transitions",
+        "column_transitions",
+        "holes",
+        "wells",
+        "bumpiness",
+        "aggregate_height",
+    ];
+
+    pub fn to_array(&self) -> [f32; FEATURE_COUNT] {
+        [
+            self.landing_height,
+            self.eroded_cells,
+            self.row_transitions,
+            self.column_transitions,
+            self.holes,
+            self.wells,
+            self.bumpiness,
+            self.aggregate_height,
+        ]
+    }
+
+    pub fn from_array(values: [f32; FEATURE_COUNT]) -> Self {
+        Features {
+            landing_height: values[0],
+            eroded_cells: values[1],
+            row_transitions: values[2],
+            column_transitions: values[3],
+            holes: values[4],
+            wells: values[5],
+            bumpiness: values[6],
+            aggregate_height: values[7],
+        }
+    }
+
+    // Parse features (or weights) from one 'name value' per line. Features
+    // left out are 0 and lines starting with '#' are comments.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut values = [0.0; FEATURE_COUNT];
+        for line in text.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let index = match Self::NAMES.iter().position(|n| *n == name) {
+                Some(index) => index,
+                None => return Err(format!("Unknown feature '{}'", name)),
+            };
+            values[index] = match (words.next().map(str::parse::<f32>), words.next()) {
+                (Some(Ok(value)), None) if value.is_finite() => value,
+                (Some(Ok(_)), None) => {
+                    return Err(format!("Expected a finite value for '{}'", name))
+                }
+                _ => return Err(format!("Expected a single value for '{}'", name)),
+            };
+        }
+        Ok(Self::from_array(values))
+    }
+
+    pub fn to_text(&self) -> String {
+        Self::NAMES
+            .iter()
+            .zip(self.to_array().iter())
+            .map(|(name, value)| format!("{} {}\n", name, value))
+            .collect()
+    }
+
+    // Sum of the features times their weights
+    pub fn dot(&self, weights: &Features) -> f32 {
+        self.to_array()
+            .iter()
+            .zip(weights.to_array().iter())
+            .map(|(value, weight)| value * weight)
+            .sum()
+    }
+
+    // Measure the features of placing a figure. The playfield is left
+    // with the figure placed and its full lines thrown away.
+    pub fn measure(pf: &mut Playfield, fig: &Figure, pos: Position) -> Self {
+        let height = pf.height() as i32;
+        let blocks: Vec<(i32, i32)> = fig
+            .face(pos.dir())
+            .iter()
+            .map(|(x, y, _)| (i32::from(*x) + pos.x(), i32::from(*y) + pos.y()))
+            .collect();
+        let top = blocks.iter().map(|b| b.1).min().unwrap_or(0);
+        let bottom = blocks.iter().map(|b| b.1).max().unwrap_or(0);
+
+        fig.place(pf, pos);
+        let mut full_lines = pf.locked_lines();
+        full_lines.sort();
+        let cleared_blocks = blocks
+            .iter()
+            .filter(|(_, y)| full_lines.contains(&(*y as u32)))
+            .count();
+        for line in &full_lines {
+            pf.throw_line(*line);
+        }
+
+        let heights = column_heights(pf);
+        Features {
+            landing_height: (height - 1 - bottom) as f32 + (bottom - top) as f32 / 2.0,
+            eroded_cells: (full_lines.len() * cleared_blocks) as f32,
+            row_transitions: get_pf_row_jitter(pf) as f32,
+            column_transitions: get_pf_col_jitter(pf) as f32,
+            holes: count_holes(pf, &heights) as f32,
+            wells: count_wells(pf, &heights) as f32,
+            bumpiness: heights
+                .windows(2)
+                .map(|pair| (pair[0] as i32 - pair[1] as i32).abs())
+                .sum::<i32>() as f32,
+            aggregate_height: heights.iter().sum::<u32>() as f32,
+        }
+    }
+}
+
+fn column_heights(pf: &Playfield) -> Vec<u32> {
+    (0..pf.width() as i32)
+        .map(|x| {
+            (0..pf.height() as i32)
+                .find(|y| pf.get_block((x, *y).into()).is_set())
+                .map_or(0, |y| pf.height() - y as u32)
+        })
+        .collect()
+}
+
+fn count_holes(pf: &Playfield, heights: &[u32]) -> u32 {
+    let mut holes = 0;
+    for (x, column_height) in heights.iter().enumerate() {
+        for y in (pf.height() - column_height)..pf.height() {
+            if !pf.get_block((x as i32, y as i32).into()).is_set() {
+                holes += 1;
+            }
+        }
+    }
+    holes
+}
+
+// Wells are the open blocks of a column with set blocks (or walls) on
+// both sides
+fn count_wells(pf: &Playfield, heights: &[u32]) -> u32 {
+    let is_set =
+        |x: i32, y: i32| x < 0 || x >= pf.width() as i32 || pf.get_block((x, y).into()).is_set();
+    let mut wells = 0;
+    for (x, column_height) in heights.iter().enumerate() {
+        let x = x as i32;
+        let mut depth = 0;
+        for y in 0..(pf.height() - column_height) as i32 {
+            if is_set(x - 1, y) && is_set(x + 1, y) {
+                depth += 1;
+                wells += depth;
+            } else {
+                depth = 0;
+            }
+        }
+    }
+    wells
+}
+
+//
+// Rates placings by a weighted sum of the features of the playfield they
+// leave, e.g. with the weights of Dellacherie or El-Tetris
+//
+pub struct WeightedComputer {
+    weights: Features,
+    pf: Option<Playfield>,
+}
+
+impl WeightedComputer {
+    pub fn new(weights: Features) -> Self {
+        WeightedComputer { weights, pf: None }
+    }
+
+    // Pierre Dellacherie's hand tuned weights
+    pub fn dellacherie() -> Self {
+        Self::new(Features {
+            landing_height: -1.0,
+            eroded_cells: 1.0,
+            row_transitions: -1.0,
+            column_transitions: -1.0,
+            holes: -4.0,
+            wells: -1.0,
+            ..Features::default()
+        })
+    }
+
+    // Dellacherie's features with the weights Islam El-Ashi found for
+    // El-Tetris by particle swarm optimization
+    pub fn el_tetris() -> Self {
+        Self::new(Features {
+            landing_height: -4.500_159,
+            eroded_cells: 3.418_127,
+            row_transitions: -3.217_888,
+            column_transitions: -9.348_695,
+            holes: -7.899_265,
+            wells: -3.385_597,
+            ..Features::default()
+        })
+    }
+
+    pub fn weights(&self) -> &Features {
+        &self.weights
+    }
+
+    // Features of a placing on the given playfield
+    pub fn features(&mut self, current_pf: &Playfield, fig: &Figure, pos: Position) -> Features {
+        let pf = self.pf.get_or_insert_with(|| current_pf.clone());
+        pf.copy(current_pf);
+        Features::measure(pf, fig, pos)
+    }
+}
+
+impl ComputerType for WeightedComputer {
+    fn init_eval(&mut self, _pf: &Playfield, _avail_placings: usize) {}
+
+    fn eval_placing(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> f32 {
+        self.features(pf, fig, pos).dot(&self.weights)
+    }
+
+    fn explain_placing(
+        &mut self,
+        pf: &Playfield,
+        fig: &Figure,
+        pos: Position,
+    ) -> Vec<(&'static str, f32)> {
+        let features = self.features(pf, fig, pos).to_array();
+        let weights = self.weights.to_array();
+        (0..FEATURE_COUNT)
+            .map(|i| (Features::NAMES[i], features[i] * weights[i]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_playfield::*;
+    use crate::test_helpers::*;
+
+    fn measure(pf: &str, pos: (i32, i32)) -> (Features, String) {
+        let o = figure(O);
+        let mut pf = playfield_from_ascii(pf).unwrap();
+        let features = Features::measure(&mut pf, &o, Position::new((pos.0, pos.1, 0)));
+        (features, playfield_to_ascii(&pf))
+    }
+
+    #[test]
+    fn measure_features() {
+        let (features, pf) = measure("....\n....\n1...\n1.11\n", (1, 1));
+        assert_eq!(pf, "....\n.44.\n144.\n1.11\n");
+        assert_eq!(
+            features,
+            Features {
+                landing_height: 1.5,
+                eroded_cells: 0.0,
+                row_transitions: 10.0,
+                column_transitions: 6.0,
+                holes: 1.0,
+                wells: 4.0,
+                bumpiness: 3.0,
+                aggregate_height: 9.0,
+            }
+        );
+    }
+
+    #[test]
+    fn weights_text() {
+        let weights = WeightedComputer::el_tetris().weights;
+        assert_eq!(Features::from_text(&weights.to_text()), Ok(weights));
+        assert_eq!(
+            Features::from_text("# Comment\nholes -2.5\n"),
+            Ok(Features {
+                holes: -2.5,
+                ..Features::default()
+            })
+        );
+        assert!(Features::from_text("height 1").is_err());
+        assert!(Features::from_text("holes").is_err());
+        assert!(Features::from_text("holes 1 2").is_err());
+        assert!(Features::from_text("holes nan").is_err());
+        assert!(Features::from_text("holes -inf").is_err());
+    }
+
+    #[test]
+    fn measure_eroded_cells() {
+        let (features, pf) = measure("....\n11..\n", (2, 0));
+        assert_eq!(pf, "....\n..44\n");
+        assert_eq!(features.landing_height, 0.5);
+        assert_eq!(features.eroded_cells, 2.0);
+        assert_eq!(features.holes, 0.0);
+    }
+}